@@ -7,13 +7,13 @@ extern crate clap;
 extern crate racer;
 
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
-use racer::{Coordinate, FileCache, Match, MatchType, Point, Session};
+use racer::{Coordinate, DepsResolver, FileCache, Match, MatchType, Point, Session};
 use std::fs::File;
 use std::io::{self, BufRead, Read};
 use std::path::{Path, PathBuf};
 
 fn point(cfg: Config) {
-    let cache = FileCache::default();
+    let cache = cfg.cache();
     let session = Session::new(&cache);
     cfg.interface.emit(Message::Coords(cfg.coords()));
     racer::to_point(cfg.coords(), cfg.expect_file(), &session)
@@ -22,7 +22,7 @@ fn point(cfg: Config) {
 }
 
 fn coord(cfg: Config) {
-    let cache = FileCache::default();
+    let cache = cfg.cache();
     let session = Session::new(&cache);
     cfg.interface.emit(Message::Point(cfg.point));
     racer::to_coords(cfg.point, cfg.expect_file(), &session)
@@ -146,7 +146,7 @@ fn run_the_complete_fn(cfg: &Config, print_type: CompletePrinter) {
     let fn_path = cfg.fn_name.as_ref().unwrap();
     let substitute_file = cfg.substitute_file.as_ref().unwrap_or(fn_path);
 
-    let cache = FileCache::default();
+    let cache = cfg.cache();
     let session = Session::new(&cache);
 
     load_query_file(&fn_path, &substitute_file, &session);
@@ -170,7 +170,7 @@ fn run_the_complete_fn(cfg: &Config, print_type: CompletePrinter) {
 /// Completes a fully qualified name specified on command line
 fn external_complete(cfg: Config, print_type: CompletePrinter) {
     let cwd = Path::new(".");
-    let cache = FileCache::default();
+    let cache = cfg.cache();
     let session = Session::new(&cache);
 
     for m in racer::complete_fully_qualified_name(cfg.fqn.as_ref().unwrap(), &cwd, &session) {
@@ -184,7 +184,7 @@ fn external_complete(cfg: Config, print_type: CompletePrinter) {
 fn prefix(cfg: Config) {
     let fn_path = cfg.fn_name.as_ref().unwrap();
     let substitute_file = cfg.substitute_file.as_ref().unwrap_or(fn_path);
-    let cache = FileCache::default();
+    let cache = cfg.cache();
     let session = Session::new(&cache);
 
     // Cache query file in session
@@ -202,7 +202,7 @@ fn prefix(cfg: Config) {
 fn find_definition(cfg: Config) {
     let fn_path = cfg.fn_name.as_ref().unwrap();
     let substitute_file = cfg.substitute_file.as_ref().unwrap_or(fn_path);
-    let cache = FileCache::default();
+    let cache = cfg.cache();
     let session = Session::new(&cache);
 
     // Cache query file in session
@@ -212,14 +212,57 @@ fn find_definition(cfg: Config) {
     cfg.interface.emit(Message::End);
 }
 
-fn validate_rust_src_path_env_var() {
-    match racer::get_rust_src_path() {
-        Ok(_) => (),
-        Err(err) => {
-            println!("{}", err);
-            std::process::exit(1);
+/// Checks that the rust source tree of the command is found.
+/// The daemon checks each command it reads instead, and indexing needs none.
+fn validate_rust_src_path(m: &ArgMatches) -> Result<(), racer::RustSrcPathError> {
+    let target = match m.subcommand() {
+        ("daemon", _) | ("index", _) => return Ok(()),
+        (_, Some(sub_m)) => sub_m.value_of("path").map(PathBuf::from),
+        _ => None,
+    };
+    racer::get_rust_src_path().map(|_| ()).or_else(|err| {
+        // the project of the target file may select a toolchain with rust-src
+        // in a `rust-toolchain` file
+        let project_src = std::env::current_dir().ok().and_then(|cwd| {
+            let from = match target {
+                Some(path) => cwd.join(path),
+                None => cwd,
+            };
+            racer::get_project_rust_src_path(&from)
+        });
+        project_src.map(|_| ()).ok_or(err)
+    })
+}
+
+fn index(cfg: Config, m: &ArgMatches) {
+    let cache = cfg.cache();
+    let session = Session::new(&cache);
+    let roots: Vec<PathBuf> = match m.values_of("path") {
+        Some(paths) => paths.map(PathBuf::from).collect(),
+        None => {
+            let cwd = std::env::current_dir().expect("current directory");
+            match racer::default_index_roots(&cwd, &session) {
+                Ok(roots) => roots,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
+    let mut failed = false;
+    for root in roots {
+        match racer::build_index(&root, &session) {
+            Ok(n) => println!("indexed {} files in {}", n, root.display()),
+            Err(err) => {
+                eprintln!("failed to index {}: {}", root.display(), err);
+                failed = true;
+            }
         }
     }
+    if failed {
+        std::process::exit(1);
+    }
 }
 
 fn daemon(cfg: Config) {
@@ -236,7 +279,10 @@ fn daemon(cfg: Config) {
             Interface::Text => cli.get_matches_from(input.trim_right().split_whitespace()),
             Interface::TabText => cli.get_matches_from(input.trim_right().split('\t')),
         };
-        run(matches, cfg.interface);
+        match validate_rust_src_path(&matches) {
+            Ok(()) => run(matches, cfg.interface, cfg.offline),
+            Err(err) => eprintln!("{}", err),
+        }
 
         input.clear();
     }
@@ -393,6 +439,7 @@ struct Config {
     substitute_file: Option<PathBuf>,
     interface: Interface,
     point: usize,
+    offline: bool,
 }
 
 impl Config {
@@ -406,6 +453,14 @@ impl Config {
     fn expect_file(&self) -> &PathBuf {
         self.fn_name.as_ref().expect("File path required")
     }
+
+    fn cache(&self) -> FileCache {
+        let mut cache = FileCache::default();
+        if self.offline {
+            cache.set_deps_resolver(DepsResolver::Offline);
+        }
+        cache
+    }
 }
 
 impl<'a> From<&'a ArgMatches<'a>> for Config {
@@ -472,6 +527,11 @@ fn build_cli<'a, 'b>() -> App<'a, 'b> {
                 .value_name("mode")
                 .help("Interface mode"),
         )
+        .arg(
+            Arg::with_name("offline")
+                .long("offline")
+                .help("Resolve dependencies from Cargo.lock without running cargo"),
+        )
         .subcommand(
             SubCommand::with_name("complete")
             .about("performs completion and returns matches")
@@ -563,6 +623,18 @@ fn build_cli<'a, 'b>() -> App<'a, 'b> {
                 .arg(Arg::with_name("substitute_file").help("An optional substitute file"))
                 .arg(Arg::with_name("linenum").help("The line number at which to find the match")),
         )
+        .subcommand(
+            SubCommand::with_name("index")
+                .about("builds the on-disk index of source roots to speed up searches")
+                .arg(
+                    Arg::with_name("path")
+                        .help(
+                            "The source roots to index. Defaults to the rust source tree \
+                             and the dependencies of the package in the current directory",
+                        )
+                        .multiple(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("point")
             .about("converts linenum and charnum in a file to a point")
@@ -602,17 +674,23 @@ fn main() {
         Some("text") | _ => Interface::Text,
     };
 
-    validate_rust_src_path_env_var();
+    let offline = matches.is_present("offline");
+
+    if let Err(err) = validate_rust_src_path(&matches) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
 
-    run(matches, interface);
+    run(matches, interface, offline);
 }
 
-fn run(m: ArgMatches, interface: Interface) {
+fn run(m: ArgMatches, interface: Interface, offline: bool) {
     use CompletePrinter::{Normal, WithSnippets};
     // match raw subcommand, and get it's sub-matches "m"
     if let (name, Some(sub_m)) = m.subcommand() {
         let mut cfg = Config::from(sub_m);
         cfg.interface = interface;
+        cfg.offline = offline;
         match name {
             "daemon" => daemon(cfg),
             "prefix" => prefix(cfg),
@@ -621,6 +699,7 @@ fn run(m: ArgMatches, interface: Interface) {
             "find-definition" => find_definition(cfg),
             "point" => point(cfg),
             "coord" => coord(cfg),
+            "index" => index(cfg, sub_m),
             _ => unreachable!(),
         }
     }
@@ -1,4 +1,5 @@
 use core::{self, Match, MatchType, Point, Scope, Session, SessionExt, SourceByteRange, Ty};
+use matchers::PendingImports;
 use nameres::{self, resolve_path_with_str};
 use scopes;
 use typeinf;
@@ -8,13 +9,14 @@ use std::rc::Rc;
 
 use syntax::ast::{
     self, ExprKind, FunctionRetTy, GenericParam, Generics, ItemKind, LitKind, PatKind, TyKind,
-    TyParamBound, TyParamBounds, UseTree, UseTreeKind,
+    TyParamBound, TyParamBounds, UseTree, UseTreeKind, WherePredicate,
 };
 use syntax::codemap::{self, FileName, Span};
 use syntax::errors::{emitter::ColorConfig, Handler};
 use syntax::parse::parser::Parser;
 use syntax::parse::{self, ParseSess};
 use syntax::print::pprust;
+use syntax::ptr::P;
 use syntax::{self, symbol, visit};
 
 /// construct parser from string
@@ -224,6 +226,22 @@ fn to_racer_ty(ty: &ast::Ty, scope: &Scope) -> Option<Ty> {
         TyKind::Array(ref ty, ref expr) => to_racer_ty(ty, scope)
             .map(|racer_ty| Ty::FixedLengthVec(Box::new(racer_ty), pprust::expr_to_string(expr))),
         TyKind::Slice(ref ty) => to_racer_ty(ty, scope).map(|ref_ty| Ty::Vec(Box::new(ref_ty))),
+        TyKind::Ptr(ref mt) => {
+            let types = match mt.ty.node {
+                TyKind::Path(_, ref path) => vec![to_racer_path(path)],
+                _ => Vec::new(),
+            };
+            let path = core::Path {
+                global: false,
+                segments: vec![core::PathSegment {
+                    name: ptr_name(mt.mutbl).to_owned(),
+                    types: types,
+                }],
+            };
+            Some(Ty::PathSearch(path, scope.clone()))
+        }
+        TyKind::TraitObject(ref bounds, _) => Some(trait_object_to_ty(ty, bounds, scope)),
+        TyKind::Paren(ref ty) => to_racer_ty(ty, scope),
         TyKind::Never => None,
         _ => {
             trace!("unhandled Ty node: {:?}", ty.node);
@@ -232,6 +250,33 @@ fn to_racer_ty(ty: &ast::Ty, scope: &Scope) -> Option<Ty> {
     }
 }
 
+/// Name of the builtin raw pointer type
+fn ptr_name(mutbl: ast::Mutability) -> &'static str {
+    match mutbl {
+        ast::Mutability::Immutable => "*const T",
+        ast::Mutability::Mutable => "*mut T",
+    }
+}
+
+/// Converts a trait object type like `dyn Handler + Send` into a match,
+/// which has the traits as its bounds
+fn trait_object_to_ty(ty: &ast::Ty, bounds: &TyParamBounds, scope: &Scope) -> Ty {
+    let tystr = pprust::ty_to_string(ty);
+    Ty::Match(Match {
+        matchstr: tystr.clone(),
+        filepath: scope.filepath.clone(),
+        point: scope.point,
+        coords: None,
+        local: false,
+        mtype: MatchType::TraitBounds(TraitBounds::from_trait_object(bounds, scope)),
+        contextstr: tystr,
+        generic_args: Vec::new(),
+        generic_types: Vec::new(),
+        docs: String::new(),
+        trait_path: None,
+    })
+}
+
 fn point_is_in_span(point: u32, span: &Span) -> bool {
     let (lo, hi) = destruct_span(*span);
     point >= lo && point < hi
@@ -492,10 +537,20 @@ fn to_racer_path(path: &ast::Path) -> core::Path {
                     data.inputs.iter().chain(data.output.iter()).collect()
                 }
             };
-            seg_types.into_iter().for_each(|ty| {
-                if let TyKind::Path(_, ref path) = ty.node {
-                    types.push(to_racer_path(path));
+            seg_types.into_iter().for_each(|ty| match ty.node {
+                TyKind::Path(_, ref path) => types.push(to_racer_path(path)),
+                // the methods of `Box<dyn Trait + Send>` are reached through `Deref`
+                // to its first trait
+                TyKind::TraitObject(ref bounds, _) => {
+                    let trait_path = bounds.iter().filter_map(|bound| match *bound {
+                        TyParamBound::TraitTyParamBound(ref ptrait_ref, _) => {
+                            Some(&ptrait_ref.trait_ref.path)
+                        }
+                        _ => None,
+                    });
+                    types.extend(trait_path.take(1).map(to_racer_path));
                 }
+                _ => {}
             });
         }
         v.push(core::PathSegment {
@@ -519,6 +574,117 @@ fn path_to_match(ty: Ty, session: &Session) -> Option<Ty> {
     }
 }
 
+/// Returns the element type of `ty[index]`.
+fn get_index_output(ty: Ty, session: &Session) -> Option<Ty> {
+    debug!("get_index_output {:?}", ty);
+    match ty {
+        Ty::RefPtr(ty) => get_index_output(*ty, session),
+        Ty::Vec(ty) | Ty::FixedLengthVec(ty, _) => path_to_match(*ty, session),
+        Ty::Match(m) => {
+            // Index impls of these are generic over `SliceIndex`, so just take the element type
+            if m.matchstr == "Vec" || m.matchstr == "VecDeque" {
+                return m.generic_types
+                    .first()
+                    .and_then(|ps| find_type_match(&ps.path, &ps.filepath, ps.point, session));
+            }
+            search_for_output_type(&m, "Index", None, session)
+        }
+        _ => None,
+    }
+}
+
+/// Checks if the operator impl for `lhs` takes a right operand of type `rhs`.
+/// Its `Rhs` type parameter is kept after the `Output` type by `search_for_impls`.
+fn is_impl_for_rhs(imp: &Match, lhs: &Match, rhs: &Match) -> bool {
+    match imp.generic_types.get(1).and_then(|ps| ps.path.segments.last()) {
+        Some(seg) if seg.name == "Self" => rhs.matchstr == lhs.matchstr,
+        Some(seg) => seg.name == rhs.matchstr,
+        None => false,
+    }
+}
+
+/// Returns the type of `ty[range]`, which is a slice, or `str` for strings.
+fn get_range_index_output(ty: Ty, session: &Session) -> Option<Ty> {
+    debug!("get_range_index_output {:?}", ty);
+    match ty {
+        Ty::RefPtr(ty) => get_range_index_output(*ty, session),
+        Ty::Vec(ty) | Ty::FixedLengthVec(ty, _) => Some(Ty::Vec(ty)),
+        Ty::Match(m) => match m.matchstr.as_str() {
+            "Vec" => m.generic_types.first().map(|ps| {
+                let scope = Scope {
+                    filepath: ps.filepath.clone(),
+                    point: ps.point,
+                };
+                Ty::Vec(Box::new(Ty::PathSearch(ps.path.clone(), scope)))
+            }),
+            "String" | "str" => {
+                let str_path = core::Path::from_vec(false, vec!["str"]);
+                find_type_match(&str_path, &m.filepath, m.point, session)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the type of an operator expression, using the impl of `trait_name` for `ty`,
+/// whose right operand is of type `rhs` if it's known.
+fn get_op_output(ty: Ty, trait_name: &str, rhs: Option<&Ty>, session: &Session) -> Option<Ty> {
+    debug!("get_op_output {:?} {} {:?}", ty, trait_name, rhs);
+    match ty {
+        Ty::RefPtr(ty) => get_op_output(*ty, trait_name, rhs, session),
+        Ty::Match(m) => {
+            // operators on primitives return the operand type
+            if m.mtype == MatchType::Builtin {
+                return Some(Ty::Match(m));
+            }
+            search_for_output_type(&m, trait_name, rhs, session)
+        }
+        Ty::Unsupported => Some(Ty::Unsupported),
+        _ => None,
+    }
+}
+
+/// Searches the `Output` associated type of the impl of `trait_name` for `m`.
+/// Of several impls, the one for the type of the right operand `rhs` is preferred.
+fn search_for_output_type(
+    m: &Match,
+    trait_name: &str,
+    rhs: Option<&Ty>,
+    session: &Session,
+) -> Option<Ty> {
+    let mut impls: Vec<_> = nameres::search_for_impls(
+        m.point,
+        &m.matchstr,
+        &m.filepath,
+        m.local,
+        true,
+        session,
+        &PendingImports::empty(),
+    ).filter(|imp| imp.matchstr == trait_name)
+        .collect();
+    if let Some(&Ty::Match(ref rhs)) = rhs {
+        if let Some(i) = impls.iter().position(|imp| is_impl_for_rhs(imp, m, rhs)) {
+            let imp = impls.remove(i);
+            impls.insert(0, imp);
+        }
+    }
+    impls
+        .into_iter()
+        .filter_map(|imp| {
+            let output = imp.generic_types.into_iter().nth(0)?;
+            if output.path.segments.len() == 1 && output.path.segments[0].name == "Self" {
+                return Some(Ty::Match(m.clone()));
+            }
+            let scope = Scope {
+                filepath: output.filepath,
+                point: output.point,
+            };
+            path_to_match_including_generics(Ty::PathSearch(output.path, scope), m, session)
+        })
+        .nth(0)
+}
+
 fn find_type_match(path: &core::Path, fpath: &Path, pos: Point, session: &Session) -> Option<Ty> {
     debug!("find_type_match {:?}, {:?}", path, fpath);
     let res = resolve_path_with_str(
@@ -612,9 +778,51 @@ impl<'c, 's, 'ast> visit::Visitor<'ast> for ExprTypeVisitor<'c, 's> {
         );
         //walk_expr(self, ex, e)
         match expr.node {
-            ExprKind::Unary(_, ref expr) | ExprKind::AddrOf(_, ref expr) => {
+            ExprKind::Unary(op, ref expr) => {
+                self.visit_expr(expr);
+                let trait_name = match op {
+                    ast::UnOp::Deref => return,
+                    ast::UnOp::Not => "Not",
+                    ast::UnOp::Neg => "Neg",
+                };
+                self.result = self.result
+                    .take()
+                    .and_then(|ty| get_op_output(ty, trait_name, None, self.session));
+            }
+            ExprKind::AddrOf(_, ref expr) => {
                 self.visit_expr(expr);
             }
+            ExprKind::Binary(op, ref lhs, ref rhs) => {
+                let trait_name = match op.node {
+                    ast::BinOpKind::Add => "Add",
+                    ast::BinOpKind::Sub => "Sub",
+                    ast::BinOpKind::Mul => "Mul",
+                    ast::BinOpKind::Div => "Div",
+                    ast::BinOpKind::Rem => "Rem",
+                    ast::BinOpKind::BitAnd => "BitAnd",
+                    ast::BinOpKind::BitOr => "BitOr",
+                    ast::BinOpKind::BitXor => "BitXor",
+                    ast::BinOpKind::Shl => "Shl",
+                    ast::BinOpKind::Shr => "Shr",
+                    // comparisons and lazy boolean operators
+                    _ => {
+                        self.result = find_type_match(
+                            &core::Path::from_vec(false, vec!["bool"]),
+                            &self.scope.filepath,
+                            self.scope.point,
+                            self.session,
+                        ).or(Some(Ty::Unsupported));
+                        return;
+                    }
+                };
+                self.visit_expr(rhs);
+                let rhs_ty = self.result.take();
+                self.visit_expr(lhs);
+                let session = self.session;
+                self.result = self.result
+                    .take()
+                    .and_then(|ty| get_op_output(ty, trait_name, rhs_ty.as_ref(), session));
+            }
             ExprKind::Path(_, ref path) => {
                 debug!("expr is a path {:?}", to_racer_path(path));
                 let codemap::BytePos(lo) = path.span.lo();
@@ -628,15 +836,35 @@ impl<'c, 's, 'ast> visit::Visitor<'ast> for ExprTypeVisitor<'c, 's> {
                     typeinf::get_type_of_match(m, msrc.as_src(), self.session)
                 });
             }
-            ExprKind::Call(ref callee_expression, _ /*ref arguments*/) => {
+            ExprKind::Call(ref callee_expression, ref arguments) => {
                 self.visit_expr(callee_expression);
 
+                let turbofish = match callee_expression.node {
+                    ExprKind::Path(_, ref path) => path.segments
+                        .last()
+                        .map(turbofish_types)
+                        .unwrap_or_default(),
+                    _ => Vec::new(),
+                };
                 self.result = self.result.take().and_then(|m| {
                     if let Ty::Match(m) = m {
                         match m.mtype {
                             MatchType::Function => {
+                                let bindings =
+                                    self.bind_fn_generics(&m, turbofish, arguments, true);
                                 typeinf::get_return_type_of_function(&m, &m, self.session)
-                                    .and_then(|ty| path_to_match(ty, self.session))
+                                    .and_then(|ty| {
+                                        if bindings.is_empty() {
+                                            path_to_match(ty, self.session)
+                                        } else {
+                                            let contextm = with_generic_bindings(&m, bindings);
+                                            path_to_match_including_generics(
+                                                destruct_ty_refptr(ty),
+                                                &contextm,
+                                                self.session,
+                                            )
+                                        }
+                                    })
                             }
                             MatchType::Struct | MatchType::Enum => Some(Ty::Match(m)),
                             _ => {
@@ -670,33 +898,45 @@ impl<'c, 's, 'ast> visit::Visitor<'ast> for ExprTypeVisitor<'c, 's> {
                 // arguments[0] is receiver(e.g. self)
                 let objexpr = &arguments[0];
                 self.visit_expr(objexpr);
-
-                self.result = self.result.as_ref().and_then(|contextm| match contextm {
-                    Ty::Match(contextm) => {
-                        let omethod = nameres::search_for_impl_methods(
-                            contextm,
-                            &methodname,
-                            contextm.point,
-                            &contextm.filepath,
-                            contextm.local,
-                            core::SearchType::ExactMatch,
-                            self.session,
-                        );
-                        omethod
-                            .map(|method| {
-                                typeinf::get_return_type_of_function(
+                self.result = self.result
+                    .take()
+                    .map(|ty| destruct_ty_refptr(resolve_slice_ty(ty, self.session)));
+
+                let turbofish = turbofish_types(method_def);
+                self.result = self.result.take().and_then(|contextm| match contextm {
+                    Ty::Match(contextm) => nameres::deref_chain(contextm, self.session)
+                        .iter()
+                        .filter_map(|contextm| {
+                            nameres::search_for_impl_methods(
+                                contextm,
+                                &methodname,
+                                contextm.point,
+                                &contextm.filepath,
+                                contextm.local,
+                                core::SearchType::ExactMatch,
+                                self.session,
+                            ).filter_map(|method| {
+                                let ty = typeinf::get_return_type_of_function(
                                     &method,
                                     contextm,
                                     self.session,
-                                )
-                            })
-                            .filter_map(|ty| {
-                                ty.and_then(|ty| {
+                                )?;
+                                let bindings = self.bind_fn_generics(
+                                    &method,
+                                    turbofish.clone(),
+                                    &arguments[1..],
+                                    false,
+                                );
+                                if bindings.is_empty() {
                                     path_to_match_including_generics(ty, contextm, self.session)
-                                })
+                                } else {
+                                    let contextm = with_generic_bindings(contextm, bindings);
+                                    path_to_match_including_generics(ty, &contextm, self.session)
+                                }
                             })
-                            .nth(0)
-                    }
+                                .nth(0)
+                        })
+                        .nth(0),
                     _ => None,
                 });
             }
@@ -705,24 +945,42 @@ impl<'c, 's, 'ast> visit::Visitor<'ast> for ExprTypeVisitor<'c, 's> {
                 let fieldname = spannedident.name.to_string();
                 debug!("exprfield {}", fieldname);
                 self.visit_expr(subexpression);
-                self.result = self.result.as_ref().and_then(|structm| match structm {
-                    Ty::Match(structm) => {
-                        typeinf::get_struct_field_type(&fieldname, structm, self.session).and_then(
-                            |fieldtypepath| {
-                                find_type_match_including_generics(
-                                    &fieldtypepath,
-                                    &structm.filepath,
-                                    structm.point,
-                                    structm,
-                                    self.session,
-                                )
-                            },
-                        )
-                    }
+                self.result = self.result.take().and_then(|ty| match destruct_ty_refptr(ty) {
+                    Ty::Match(structm) => nameres::deref_chain(structm, self.session)
+                        .iter()
+                        .filter(|m| m.mtype == MatchType::Struct)
+                        .filter_map(|structm| {
+                            typeinf::get_struct_field_type(&fieldname, structm, self.session)
+                                .and_then(|fieldtypepath| {
+                                    find_type_match_including_generics(
+                                        &fieldtypepath,
+                                        &structm.filepath,
+                                        structm.point,
+                                        structm,
+                                        self.session,
+                                    )
+                                })
+                        })
+                        .nth(0),
+                    Ty::Tuple(elems) => fieldname
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|i| elems.into_iter().nth(i))
+                        .and_then(|ty| path_to_match(ty, self.session)),
                     _ => None,
                 });
             }
 
+            ExprKind::Index(ref body, ref index) => {
+                debug!("index expr");
+                self.visit_expr(body);
+                let session = self.session;
+                self.result = self.result.take().and_then(|ty| match index.node {
+                    ExprKind::Range(..) => get_range_index_output(ty, session),
+                    _ => get_index_output(ty, session),
+                });
+            }
+
             ExprKind::Tup(ref exprs) => {
                 let mut v = Vec::new();
                 for expr in exprs {
@@ -739,12 +997,20 @@ impl<'c, 's, 'ast> visit::Visitor<'ast> for ExprTypeVisitor<'c, 's> {
             }
 
             ExprKind::Lit(ref lit) => {
-                let ty_path = match lit.node {
-                    LitKind::Str(_, _) => Some(core::Path::from_vec(false, vec!["str"])),
-                    // See https://github.com/phildawes/racer/issues/727 for
-                    // information on why other literals aren't supported.
+                let ty_name = match lit.node {
+                    LitKind::Str(_, _) => Some("str"),
+                    LitKind::Int(_, ast::LitIntType::Signed(ty)) => Some(ty.ty_to_string()),
+                    LitKind::Int(_, ast::LitIntType::Unsigned(ty)) => Some(ty.ty_to_string()),
+                    // unsuffixed literals are typed as their fallback types
+                    LitKind::Int(_, ast::LitIntType::Unsuffixed) => Some("i32"),
+                    LitKind::Float(_, ty) => Some(ty.ty_to_string()),
+                    LitKind::FloatUnsuffixed(_) => Some("f64"),
+                    LitKind::Char(_) => Some("char"),
+                    LitKind::Bool(_) => Some("bool"),
+                    LitKind::Byte(_) => Some("u8"),
                     _ => None,
                 };
+                let ty_path = ty_name.map(|name| core::Path::from_vec(false, vec![name]));
 
                 self.result = if let Some(lit_path) = ty_path {
                     find_type_match(
@@ -830,6 +1096,165 @@ impl<'c, 's, 'ast> visit::Visitor<'ast> for ExprTypeVisitor<'c, 's> {
     }
 }
 
+impl<'c, 's> ExprTypeVisitor<'c, 's> {
+    /// Binds generic parameters of `fnmatch` from the turbofish and the types of arguments.
+    /// `ufcs` means `arguments` contains the receiver, like `Foo::method(foo, arg)`.
+    fn bind_fn_generics(
+        &self,
+        fnmatch: &Match,
+        turbofish: Vec<Option<core::Path>>,
+        arguments: &[P<ast::Expr>],
+        ufcs: bool,
+    ) -> Vec<(String, core::PathSearch)> {
+        let mut bindings = Vec::new();
+        let sig = match typeinf::get_function_signature(fnmatch, self.session) {
+            Some(sig) => sig,
+            None => return bindings,
+        };
+        if sig.generics.inner.is_empty() {
+            return bindings;
+        }
+        for (param, ty) in sig.generics.inner.iter().zip(turbofish) {
+            if let Some(path) = ty {
+                let path_search = core::PathSearch {
+                    path: path,
+                    filepath: self.scope.filepath.clone(),
+                    point: self.scope.point,
+                };
+                bind_generic(param.name(), path_search, &mut bindings);
+            }
+        }
+        let arguments = if ufcs && sig.has_self && !arguments.is_empty() {
+            &arguments[1..]
+        } else {
+            arguments
+        };
+        for (declared, arg) in sig.inputs.iter().zip(arguments) {
+            let declared = match *declared {
+                Some(ref ty) => ty,
+                None => continue,
+            };
+            let mut v = ExprTypeVisitor {
+                scope: self.scope.clone(),
+                result: None,
+                session: self.session,
+            };
+            v.visit_expr(arg);
+            if let Some(actual) = v.result {
+                unify_generics(declared, &actual, &sig.generics, &mut bindings);
+            }
+        }
+        debug!("[bind_fn_generics] {:?}: {:?}", fnmatch.matchstr, bindings);
+        bindings
+    }
+}
+
+/// Collects explicit generic arguments like `::<Vec<_>>`. Inferred ones(`_`) are `None`.
+fn turbofish_types(seg: &ast::PathSegment) -> Vec<Option<core::Path>> {
+    let params = match seg.parameters {
+        Some(ref params) => params,
+        None => return Vec::new(),
+    };
+    match **params {
+        ast::PathParameters::AngleBracketed(ref data) => data.types
+            .iter()
+            .map(|ty| match ty.node {
+                TyKind::Path(_, ref path) => Some(to_racer_path(path)),
+                _ => None,
+            })
+            .collect(),
+        ast::PathParameters::Parenthesized(_) => Vec::new(),
+    }
+}
+
+fn bind_generic(name: &str, ty: core::PathSearch, bindings: &mut Vec<(String, core::PathSearch)>) {
+    // the first binding wins, like the turbofish over arguments
+    if !bindings.iter().any(|&(ref n, _)| n == name) {
+        bindings.push((name.to_owned(), ty));
+    }
+}
+
+fn ty_to_path_search(ty: &Ty) -> Option<core::PathSearch> {
+    match *ty {
+        Ty::Match(ref m) => {
+            let mut path = core::Path::from_vec(false, vec![&m.matchstr]);
+            path.segments[0].types = m.generic_types.iter().map(|ps| ps.path.clone()).collect();
+            Some(core::PathSearch {
+                path: path,
+                filepath: m.filepath.clone(),
+                point: m.point,
+            })
+        }
+        Ty::PathSearch(ref path, ref scope) => Some(core::PathSearch {
+            path: path.clone(),
+            filepath: scope.filepath.clone(),
+            point: scope.point,
+        }),
+        Ty::RefPtr(ref ty) => ty_to_path_search(ty),
+        _ => None,
+    }
+}
+
+/// Binds generic parameters appearing in `declared` by comparing it with `actual`,
+/// e.g. `Vec<T>` and `Vec<Foo>` binds `T` to `Foo`
+fn unify_generics(
+    declared: &Ty,
+    actual: &Ty,
+    generics: &GenericsList,
+    bindings: &mut Vec<(String, core::PathSearch)>,
+) {
+    let is_param =
+        |path: &core::Path| path.segments.len() == 1 && path.segments[0].types.is_empty()
+            && generics.find_type_param(&path.segments[0].name).is_some();
+    match (declared, actual) {
+        (Ty::RefPtr(declared), _) => unify_generics(declared, actual, generics, bindings),
+        (_, Ty::RefPtr(actual)) => unify_generics(declared, actual, generics, bindings),
+        (Ty::Tuple(declared), Ty::Tuple(actual)) => {
+            for (d, a) in declared.iter().zip(actual) {
+                unify_generics(d, a, generics, bindings);
+            }
+        }
+        (Ty::Vec(declared), Ty::Vec(actual))
+        | (Ty::Vec(declared), Ty::FixedLengthVec(actual, _))
+        | (Ty::FixedLengthVec(declared, _), Ty::FixedLengthVec(actual, _)) => {
+            unify_generics(declared, actual, generics, bindings)
+        }
+        (Ty::PathSearch(path, _), _) => {
+            if is_param(path) {
+                if let Some(path_search) = ty_to_path_search(actual) {
+                    bind_generic(&path.segments[0].name, path_search, bindings);
+                }
+            } else if let Ty::Match(m) = actual {
+                let last = match path.segments.last() {
+                    Some(seg) => seg,
+                    None => return,
+                };
+                for (typath, gen_ty) in last.types.iter().zip(&m.generic_types) {
+                    if is_param(typath) {
+                        bind_generic(&typath.segments[0].name, gen_ty.clone(), bindings);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns a copy of `contextm` whose generic types also contain `bindings`.
+/// They are placed first, since generics of a function shadow the ones of the impl.
+fn with_generic_bindings(contextm: &Match, bindings: Vec<(String, core::PathSearch)>) -> Match {
+    let mut m = contextm.clone();
+    // generic_args and generic_types are zipped, so remove unresolved args first
+    let len = m.generic_types.len();
+    m.generic_args.truncate(len);
+    let (mut args, mut types): (Vec<_>, Vec<_>) = bindings.into_iter().unzip();
+    args.append(&mut m.generic_args);
+    types.append(&mut m.generic_types);
+    m.generic_args = args;
+    m.generic_types = types;
+    m
+}
+
 // gets generics info from the context match
 fn path_to_match_including_generics(ty: Ty, contextm: &Match, session: &Session) -> Option<Ty> {
     match ty {
@@ -900,8 +1325,11 @@ fn find_type_match_including_generics(
     assert_eq!(&structm.filepath, filepath);
     let fieldtypepath = match fieldtype {
         Ty::PathSearch(path, _) => path,
+        // trait objects are already resolved
+        Ty::Match(_) => return Some(fieldtype.clone()),
         Ty::RefPtr(ty) => match ty.as_ref() {
             Ty::PathSearch(path, _) => path,
+            Ty::Match(_) => return Some(ty.as_ref().clone()),
             _ => {
                 debug!(
                     "EXPECTING A PATH!! Cannot handle other types yet. {:?}",
@@ -1035,6 +1463,14 @@ impl<'ast> visit::Visitor<'ast> for ImplVisitor {
                         self.name_path = Some(to_racer_path(path));
                     }
                 }
+                TyKind::Slice(_) => {
+                    // the builtin slice type is searched as `[T]`
+                    self.name_path = Some(core::Path::from_vec(false, vec!["[T]"]));
+                }
+                TyKind::Ptr(ref mt) => {
+                    // and raw pointers as `*const T` or `*mut T`
+                    self.name_path = Some(core::Path::from_vec(false, vec![ptr_name(mt.mutbl)]));
+                }
                 _ => {}
             }
             otrait.as_ref().map(|t| {
@@ -1092,6 +1528,13 @@ impl TraitBounds {
             &seg[0].name == name
         })?)
     }
+    /// Names of the traits without their paths, like `Display` for `fmt::Display`
+    pub fn trait_names(&self) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter_map(|ps| ps.path.segments.last().map(|seg| seg.name.as_str()))
+            .collect()
+    }
     /// Search traits included in bounds and return Matches
     pub fn get_traits(&self, session: &Session) -> Vec<Match> {
         self.0
@@ -1108,6 +1551,25 @@ impl TraitBounds {
             })
             .collect()
     }
+    /// Bounds of a trait object. As we can't know where each trait appears in
+    /// the original source, all traits are resolved from the scope.
+    fn from_trait_object(bounds: &TyParamBounds, scope: &Scope) -> TraitBounds {
+        let vec = bounds
+            .iter()
+            .filter_map(|bound| {
+                if let TyParamBound::TraitTyParamBound(ref ptrait_ref, _) = *bound {
+                    Some(core::PathSearch {
+                        path: to_racer_path(&ptrait_ref.trait_ref.path),
+                        filepath: scope.filepath.clone(),
+                        point: scope.point,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        TraitBounds(vec)
+    }
     fn from_ty_param_bounds<P: AsRef<Path>>(
         bounds: &TyParamBounds,
         file_path: P,
@@ -1163,6 +1625,7 @@ impl GenericsArg {
             generic_args: Vec::new(),
             generic_types: Vec::new(),
             docs: String::new(),
+            trait_path: None,
         })
     }
 }
@@ -1206,7 +1669,24 @@ impl GenericsList {
                 }
             })
             .collect();
-        GenericsList { inner: res }
+        let mut list = GenericsList { inner: res };
+        // bounds in `where T: Display` are bounds of the parameter as well
+        for predicate in &generics.where_clause.predicates {
+            if let WherePredicate::BoundPredicate(ref bound) = *predicate {
+                let name = match bound.bounded_ty.node {
+                    TyKind::Path(None, ref path) if path.segments.len() == 1 => {
+                        path.segments[0].ident.name.as_str().to_string()
+                    }
+                    _ => continue,
+                };
+                if let Some(arg) = list.inner.iter_mut().find(|arg| arg.name == name) {
+                    let bounds =
+                        TraitBounds::from_ty_param_bounds(&bound.bounds, &file_path, offset);
+                    arg.bounds.0.extend(bounds.0);
+                }
+            }
+        }
+        list
     }
     pub fn get_idents(&self) -> Vec<String> {
         self.inner.iter().map(|g| g.name.to_string()).collect()
@@ -1348,6 +1828,19 @@ pub fn parse_fn_output(s: String, scope: Scope) -> Option<core::Ty> {
     v.result
 }
 
+pub fn parse_fn_signature(s: String, scope: Scope) -> Option<FnSignature> {
+    let mut v = FnSignatureVisitor {
+        scope: scope,
+        generics: GenericsList::default(),
+        result: None,
+    };
+    with_stmt(s, |stmt| visit::walk_stmt(&mut v, stmt));
+    v.result.map(|mut sig| {
+        sig.generics = v.generics;
+        sig
+    })
+}
+
 pub fn parse_fn_arg_type(
     s: String,
     argpos: Point,
@@ -1445,6 +1938,49 @@ impl<'ast> visit::Visitor<'ast> for FnOutputVisitor {
     }
 }
 
+/// Generics, argument types and the return type of a function
+#[derive(Debug)]
+pub struct FnSignature {
+    pub generics: GenericsList,
+    /// whether the first argument is `self`
+    pub has_self: bool,
+    /// types of arguments except `self`
+    pub inputs: Vec<Option<Ty>>,
+    pub output: Option<Ty>,
+}
+
+pub struct FnSignatureVisitor {
+    scope: Scope,
+    generics: GenericsList,
+    result: Option<FnSignature>,
+}
+
+impl<'ast> visit::Visitor<'ast> for FnSignatureVisitor {
+    fn visit_generics(&mut self, g: &'ast Generics) {
+        let generics = GenericsList::from_generics(g, &self.scope.filepath, 0);
+        self.generics.append(generics);
+    }
+
+    fn visit_fn(&mut self, _: visit::FnKind, fd: &ast::FnDecl, _: codemap::Span, _: ast::NodeId) {
+        let has_self = fd.inputs.first().map_or(false, |arg| arg.is_self());
+        let inputs = fd.inputs
+            .iter()
+            .filter(|arg| !arg.is_self())
+            .map(|arg| to_racer_ty(&arg.ty, &self.scope))
+            .collect();
+        let output = match fd.output {
+            FunctionRetTy::Ty(ref ty) => to_racer_ty(ty, &self.scope),
+            FunctionRetTy::Default(_) => None,
+        };
+        self.result = Some(FnSignature {
+            generics: GenericsList::default(),
+            has_self: has_self,
+            inputs: inputs,
+            output: output,
+        });
+    }
+}
+
 /// Visitor to detect type of fnarg
 pub struct FnArgTypeVisitor<'c: 's, 's> {
     /// the code point arg appears in search string
@@ -1508,6 +2044,21 @@ impl<'c, 's, 'ast> visit::Visitor<'ast> for FnArgTypeVisitor<'c, 's> {
     }
 }
 
+/// Resolves the element type of `[T]` or `[T; N]` to the builtin slice type.
+pub fn get_slice_match(elem: &Ty, session: &Session) -> Option<Match> {
+    ty_to_path_search(elem).and_then(|elem| nameres::get_slice_match(elem, session))
+}
+
+// slices and arrays are resolved to the builtin slice type, so that we can call methods on them
+fn resolve_slice_ty(ty: Ty, session: &Session) -> Ty {
+    match destruct_ty_refptr(ty.clone()) {
+        Ty::Vec(elem) | Ty::FixedLengthVec(elem, _) => {
+            get_slice_match(&elem, session).map_or(ty, Ty::Match)
+        }
+        _ => ty,
+    }
+}
+
 fn destruct_ty_refptr(ty_arg: Ty) -> Ty {
     if let Ty::RefPtr(ty) = ty_arg {
         destruct_ty_refptr(*ty)
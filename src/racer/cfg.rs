@@ -0,0 +1,322 @@
+// Evaluation of `#[cfg(...)]` attributes
+
+use core::{Session, SessionExt, Src};
+use fileres;
+use std::collections::HashSet;
+use std::env;
+use std::path::Path;
+
+/// The configuration `#[cfg(...)]` attributes are evaluated against.
+///
+/// By default it describes the host, with the default features of the
+/// package the file belongs to.
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    /// e.g. `linux`, `macos` or `windows`
+    pub target_os: String,
+    /// `unix` or `windows`
+    pub target_family: String,
+    /// features to enable instead of the default features of the package
+    pub features: Option<HashSet<String>>,
+}
+
+impl Default for Cfg {
+    fn default() -> Self {
+        Cfg {
+            target_os: env::consts::OS.to_owned(),
+            target_family: env::consts::FAMILY.to_owned(),
+            features: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum CfgPredicate {
+    /// e.g. `unix`
+    Name(String),
+    /// e.g. `feature = "serde"`
+    KeyValue(String, String),
+    All(Vec<CfgPredicate>),
+    Any(Vec<CfgPredicate>),
+    Not(Box<CfgPredicate>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Eq,
+    Comma,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '=' => tokens.push(Token::Eq),
+            ',' => tokens.push(Token::Comma),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            _ if c.is_whitespace() => {}
+            _ if c.is_alphanumeric() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+fn parse_predicate(tokens: &[Token], pos: &mut usize) -> Option<CfgPredicate> {
+    let name = match *tokens.get(*pos)? {
+        Token::Ident(ref name) => name.clone(),
+        _ => return None,
+    };
+    *pos += 1;
+    match tokens.get(*pos) {
+        Some(&Token::Eq) => match *tokens.get(*pos + 1)? {
+            Token::Str(ref value) => {
+                *pos += 2;
+                Some(CfgPredicate::KeyValue(name, value.clone()))
+            }
+            _ => None,
+        },
+        Some(&Token::Open) => {
+            *pos += 1;
+            let mut preds = Vec::new();
+            loop {
+                match *tokens.get(*pos)? {
+                    Token::Close => {
+                        *pos += 1;
+                        break;
+                    }
+                    Token::Comma => *pos += 1,
+                    _ => preds.push(parse_predicate(tokens, pos)?),
+                }
+            }
+            match name.as_str() {
+                "all" => Some(CfgPredicate::All(preds)),
+                "any" => Some(CfgPredicate::Any(preds)),
+                "not" if preds.len() == 1 => Some(CfgPredicate::Not(Box::new(preds.remove(0)))),
+                _ => None,
+            }
+        }
+        _ => Some(CfgPredicate::Name(name)),
+    }
+}
+
+/// Parses the predicate of an attribute like `#[cfg(unix)]`
+fn parse_cfg_attr(attr: &str) -> Option<CfgPredicate> {
+    let attr = attr.trim();
+    if !attr.starts_with("#[") || !attr.ends_with(']') {
+        return None;
+    }
+    let tokens = tokenize(&attr[2..attr.len() - 1])?;
+    match *tokens.first()? {
+        Token::Ident(ref name) if name == "cfg" => {}
+        _ => return None,
+    }
+    let mut pos = 1;
+    if tokens.get(pos)? != &Token::Open {
+        return None;
+    }
+    pos += 1;
+    let pred = parse_predicate(&tokens, &mut pos)?;
+    if tokens.get(pos)? != &Token::Close || pos + 1 != tokens.len() {
+        return None;
+    }
+    Some(pred)
+}
+
+impl Cfg {
+    /// Evaluates the predicate, returning `None` if it depends on something unknown
+    fn eval(&self, pred: &CfgPredicate, features: &HashSet<String>, test: bool) -> Option<bool> {
+        match *pred {
+            CfgPredicate::Name(ref name) => match name.as_str() {
+                "unix" | "windows" => Some(self.target_family == *name),
+                "test" => Some(test),
+                _ => None,
+            },
+            CfgPredicate::KeyValue(ref key, ref value) => {
+                // string contents are blank in masked sources
+                if value.trim().is_empty() {
+                    return None;
+                }
+                match key.as_str() {
+                    "target_os" => Some(self.target_os == *value),
+                    "target_family" => Some(self.target_family == *value),
+                    "feature" => Some(features.contains(value)),
+                    _ => None,
+                }
+            }
+            CfgPredicate::All(ref preds) => {
+                let mut res = Some(true);
+                for pred in preds {
+                    match self.eval(pred, features, test) {
+                        Some(false) => return Some(false),
+                        None => res = None,
+                        Some(true) => {}
+                    }
+                }
+                res
+            }
+            CfgPredicate::Any(ref preds) => {
+                let mut res = Some(false);
+                for pred in preds {
+                    match self.eval(pred, features, test) {
+                        Some(true) => return Some(true),
+                        None => res = None,
+                        Some(false) => {}
+                    }
+                }
+                res
+            }
+            CfgPredicate::Not(ref pred) => self.eval(pred, features, test).map(|b| !b),
+        }
+    }
+}
+
+/// Parses a crate attribute which makes the crate `no_std`. It's either `#![no_std]`,
+/// or `#![cfg_attr(.., no_std)]` which is returned along with its predicate.
+fn parse_no_std_attr(attr: &str) -> Option<Option<CfgPredicate>> {
+    let attr = attr.trim();
+    if !attr.starts_with("#![") || !attr.ends_with(']') {
+        return None;
+    }
+    let tokens = tokenize(&attr[3..attr.len() - 1])?;
+    match *tokens.first()? {
+        Token::Ident(ref name) if name == "no_std" && tokens.len() == 1 => return Some(None),
+        Token::Ident(ref name) if name == "cfg_attr" => {}
+        _ => return None,
+    }
+    if tokens.get(1)? != &Token::Open {
+        return None;
+    }
+    let mut pos = 2;
+    let pred = parse_predicate(&tokens, &mut pos)?;
+    let no_std = Token::Ident("no_std".to_owned());
+    if tokens[pos..].contains(&no_std) {
+        Some(Some(pred))
+    } else {
+        None
+    }
+}
+
+/// Checks if the crate attribute makes the crate `no_std`
+pub fn is_no_std_attr(attr: &str, filepath: &Path, session: &Session) -> bool {
+    match parse_no_std_attr(attr) {
+        Some(Some(pred)) => is_enabled(&pred, filepath, session),
+        Some(None) => true,
+        None => false,
+    }
+}
+
+/// Checks if the item following the attribute is compiled in.
+/// Attributes other than `cfg` and predicates which can't be evaluated count as enabled.
+pub fn is_cfg_enabled(attr: &str, filepath: &Path, session: &Session) -> bool {
+    match parse_cfg_attr(attr) {
+        Some(pred) => is_enabled(&pred, filepath, session),
+        None => true,
+    }
+}
+
+fn is_enabled(pred: &CfgPredicate, filepath: &Path, session: &Session) -> bool {
+    let cfg = session.cfg();
+    let test = session.is_test_context(filepath);
+    let enabled = match cfg.features {
+        Some(ref features) => cfg.eval(pred, features, test),
+        None => match fileres::get_manifest_info(filepath, session) {
+            Some(info) => cfg.eval(pred, &info.features, test),
+            None => cfg.eval(pred, &HashSet::new(), test),
+        },
+    };
+    enabled.unwrap_or(true)
+}
+
+/// Returns the text of the attribute at `start..end` of the masked source,
+/// taken from the raw file so that string values like feature names are kept.
+/// Masking keeps the offsets of the source, so the attribute is at the same points.
+pub fn attr_text(msrc: Src, start: usize, end: usize, filepath: &Path, session: &Session) -> String {
+    let raw = session.load_file(filepath);
+    raw.get(msrc.from + start..msrc.from + end)
+        .unwrap_or(&msrc[start..end])
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(attr: &str, features: &[&str]) -> Option<bool> {
+        let cfg = Cfg {
+            target_os: "linux".to_owned(),
+            target_family: "unix".to_owned(),
+            features: None,
+        };
+        let features = features.iter().map(|s| s.to_string()).collect();
+        cfg.eval(&parse_cfg_attr(attr).unwrap(), &features, false)
+    }
+
+    #[test]
+    fn parses_nested_predicates() {
+        assert_eq!(
+            parse_cfg_attr("#[cfg(all(unix, not(feature = \"a\")))]"),
+            Some(CfgPredicate::All(vec![
+                CfgPredicate::Name("unix".to_owned()),
+                CfgPredicate::Not(Box::new(CfgPredicate::KeyValue(
+                    "feature".to_owned(),
+                    "a".to_owned()
+                ))),
+            ]))
+        );
+        assert_eq!(parse_cfg_attr("#[inline]"), None);
+        assert_eq!(parse_cfg_attr("#[cfg_attr(test, derive(Debug))]"), None);
+    }
+
+    #[test]
+    fn parses_no_std_attrs() {
+        assert_eq!(parse_no_std_attr("#![no_std]"), Some(None));
+        assert_eq!(
+            parse_no_std_attr("#![cfg_attr(not(feature = \"std\"), no_std)]"),
+            Some(Some(CfgPredicate::Not(Box::new(CfgPredicate::KeyValue(
+                "feature".to_owned(),
+                "std".to_owned()
+            )))))
+        );
+        assert_eq!(parse_no_std_attr("#![cfg_attr(test, feature(test))]"), None);
+        assert_eq!(parse_no_std_attr("#![no_main]"), None);
+    }
+
+    #[test]
+    fn evaluates_predicates() {
+        assert_eq!(eval("#[cfg(unix)]", &[]), Some(true));
+        assert_eq!(eval("#[cfg(windows)]", &[]), Some(false));
+        assert_eq!(eval("#[cfg(target_os = \"macos\")]", &[]), Some(false));
+        assert_eq!(eval("#[cfg(feature = \"a\")]", &["a"]), Some(true));
+        assert_eq!(eval("#[cfg(any(windows, feature = \"b\"))]", &["a"]), Some(false));
+        assert_eq!(eval("#[cfg(not(debug_assertions))]", &[]), None);
+        assert_eq!(eval("#[cfg(any(unix, debug_assertions))]", &[]), Some(true));
+        assert_eq!(eval("#[cfg(test)]", &[]), Some(false));
+    }
+}
@@ -1,8 +1,8 @@
 use codeiter::StmtIndicesIter;
 use matchers::PendingImports;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
 use std::io::Read;
@@ -15,7 +15,10 @@ use std::{fmt, vec};
 use std::{path, str};
 
 use ast;
+use cfg::Cfg;
 use codecleaner;
+use fileres;
+use index::{self, FileIndex};
 use nameres;
 use scopes;
 use util;
@@ -98,6 +101,9 @@ pub struct Match {
     pub generic_args: Vec<String>,
     pub generic_types: Vec<PathSearch>, // generic types are evaluated lazily
     pub docs: String,
+    /// For a method of a trait which isn't in scope at the cursor, the path of the trait,
+    /// like `std::io::Write`, so that it can be imported
+    pub trait_path: Option<String>,
 }
 
 impl Match {
@@ -629,22 +635,281 @@ pub struct FileCache {
 
     /// cached dependencies(manifest_path -> DepsInfo)
     deps_map: RefCell<HashMap<path::PathBuf, Rc<DepsInfo>>>,
+
+    /// blanket impls found in cached files
+    blanket_impls_map: RefCell<HashMap<path::PathBuf, Rc<Vec<BlanketImpl>>>>,
+
+    /// macros defined in cached files and in the modules they declare
+    macro_rules_map: RefCell<HashMap<path::PathBuf, Rc<Vec<MacroRules>>>>,
+
+    /// cached package settings(manifest_path -> ManifestInfo)
+    manifest_map: RefCell<HashMap<path::PathBuf, Rc<ManifestInfo>>>,
+
+    /// manifests of the packages directories belong to(directory -> (manifest_path, modified))
+    ///
+    /// Directories not in a package aren't cached, so that a package created later is found.
+    package_manifest_map: RefCell<HashMap<path::PathBuf, (path::PathBuf, SystemTime)>>,
+
+    /// rust source trees of toolchains(toolchain file -> (rust src path, modified))
+    toolchain_map: RefCell<HashMap<path::PathBuf, (path::PathBuf, SystemTime)>>,
+
+    /// layouts of rust source trees(rust src path -> has the `library/` layout)
+    rust_src_layout_map: RefCell<HashMap<path::PathBuf, bool>>,
+
+    /// up to date indexes of files read from disk, or `None` if a file has none
+    index_map: RefCell<HashMap<path::PathBuf, Option<Rc<FileIndex>>>>,
+
+    /// the directory indexes of source roots are stored in
+    index_dir: Option<path::PathBuf>,
+
+    /// how the dependencies of packages are resolved
+    deps_resolver: DepsResolver,
+
+    /// files whose contents were given by `cache_file_contents` rather than loaded,
+    /// which may differ from the files on disk
+    unsaved_files: RefCell<HashSet<path::PathBuf>>,
+
+    /// modules of the targets of packages found by walking their module trees
+    /// (file -> (crate root, module path in the crate))
+    crate_module_map: RefCell<HashMap<path::PathBuf, (path::PathBuf, Vec<String>)>>,
+}
+
+/// Returns the module declarations in the source with comments masked, and the `#[path]`
+/// attributes which may move them, to tell if an edit changes the module tree
+fn module_decls(msrc: &str) -> Vec<&str> {
+    let mut decls = Vec::new();
+    for (i, _) in msrc.match_indices("mod") {
+        let is_keyword = !msrc[..i].ends_with(util::is_ident_char)
+            && msrc[i + 3..].starts_with(char::is_whitespace);
+        if is_keyword {
+            let end = msrc[i..]
+                .find(|c: char| c == ';' || c == '{')
+                .map_or(msrc.len(), |n| i + n);
+            decls.push(&msrc[i..end]);
+        }
+    }
+    for (i, _) in msrc.match_indices("#[path") {
+        let end = msrc[i..].find(']').map_or(msrc.len(), |n| i + n);
+        decls.push(&msrc[i..end]);
+    }
+    decls
+}
+
+#[test]
+fn module_decls_only_change_with_the_module_tree() {
+    let src = "mod shapes;\n#[path = \"util.rs\"]\npub mod helpers { fn model() {} }\n";
+    assert_eq!(
+        module_decls(src),
+        vec!["mod shapes", "mod helpers ", "#[path = \"util.rs\""]
+    );
+    assert_eq!(
+        module_decls(src),
+        module_decls(&src.replace("fn model() {}", "fn model() { let module = 1; }"))
+    );
+    assert!(module_decls(src) != module_decls(&src.replace("shapes", "figures")));
+}
+
+/// A blanket impl like `impl<T: Display> ToString for T`
+#[derive(Clone, Debug)]
+pub struct BlanketImpl {
+    /// the implemented trait
+    pub trait_path: Path,
+    /// names of the traits the type parameter is bounded by
+    pub bounds: Vec<String>,
+    /// the point where the impl starts
+    pub point: Point,
+}
+
+/// A macro defined by `macro_rules!`
+#[derive(Clone, Debug)]
+pub struct MacroRules {
+    pub name: String,
+    /// the file the macro is defined in
+    pub filepath: path::PathBuf,
+    /// the points where the bodies of its rules start
+    pub bodies: Vec<Point>,
 }
 
 /// dependencies info of a package
 #[derive(Clone, Debug)]
 pub struct DepsInfo {
-    /// dependencies of a package(libname -> src_path)
-    deps: HashMap<String, path::PathBuf>,
+    /// dependencies of a package(libname -> (src_path, kinds))
+    deps: HashMap<String, (path::PathBuf, Vec<DepKind>)>,
+    /// targets of the package(src_path, kind)
+    targets: Vec<(path::PathBuf, TargetKind)>,
     /// last modified time of Cargo.toml
     modified: SystemTime,
 }
 
+/// The section a dependency is declared in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepKind {
+    /// `[dependencies]`
+    Normal,
+    /// `[dev-dependencies]`
+    Dev,
+    /// `[build-dependencies]`
+    Build,
+    /// the library of the package itself
+    Package,
+}
+
+/// The kind of target a file is compiled in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Test,
+    Bench,
+    Example,
+    /// the build script, `build.rs`
+    Build,
+}
+
+impl TargetKind {
+    /// Checks if dependencies of the kind can be used from the target.
+    /// Unit tests of libraries and binaries can use dev-dependencies as well.
+    pub fn can_use(self, dep: DepKind, is_test: bool) -> bool {
+        match (self, dep) {
+            (TargetKind::Lib, DepKind::Dev) | (TargetKind::Bin, DepKind::Dev) if is_test => true,
+            (TargetKind::Build, DepKind::Build) => true,
+            (TargetKind::Build, _) | (_, DepKind::Build) => false,
+            (_, DepKind::Normal) => true,
+            (TargetKind::Lib, _) => false,
+            (TargetKind::Bin, DepKind::Package) => true,
+            (TargetKind::Bin, _) => false,
+            (TargetKind::Test, _) | (TargetKind::Bench, _) | (TargetKind::Example, _) => true,
+        }
+    }
+}
+
 impl DepsInfo {
-    pub fn get_src_path(&self, query: &str) -> Option<path::PathBuf> {
-        let p = self.deps.get(query)?;
-        Some(p.to_owned())
+    pub fn get_src_path(
+        &self,
+        query: &str,
+        target: TargetKind,
+        is_test: bool,
+    ) -> Option<path::PathBuf> {
+        let &(ref p, ref kinds) = self.deps.get(query)?;
+        if kinds.iter().any(|&kind| target.can_use(kind, is_test)) {
+            Some(p.to_owned())
+        } else {
+            None
+        }
+    }
+
+    /// names of the dependencies the target can use
+    pub fn names(&self, target: TargetKind, is_test: bool) -> Vec<String> {
+        self.deps
+            .iter()
+            .filter(|&(_, &(_, ref kinds))| {
+                kinds.iter().any(|&kind| target.can_use(kind, is_test))
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// directories of the sources of the dependencies, other than the package itself
+    pub fn dep_src_dirs(&self) -> Vec<path::PathBuf> {
+        let mut dirs: Vec<_> = self.deps
+            .values()
+            .filter(|&&(_, ref kinds)| !kinds.contains(&DepKind::Package))
+            .filter_map(|&(ref p, _)| p.parent().map(|dir| dir.to_owned()))
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs
     }
+
+    /// Returns the name and the root file of the dependency whose sources contain the file.
+    /// Names without hyphens are preferred, since they can be used in paths.
+    pub fn dependency_of_file(&self, filepath: &path::Path) -> Option<(String, path::PathBuf)> {
+        self.deps
+            .iter()
+            .filter_map(|(name, &(ref p, _))| {
+                let dir = p.parent()?;
+                if filepath.starts_with(dir) {
+                    Some((dir.components().count(), !name.contains('-'), name, p))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|&(depth, no_hyphen, name, _)| (depth, no_hyphen, name.clone()))
+            .map(|(_, _, name, p)| (name.clone(), p.clone()))
+    }
+
+    /// root files of the dependencies, including the library of the package itself
+    pub fn dep_roots(&self) -> Vec<path::PathBuf> {
+        let mut roots: Vec<_> = self.deps.values().map(|&(ref p, _)| p.clone()).collect();
+        roots.sort();
+        roots.dedup();
+        roots
+    }
+
+    /// root files of the targets, libraries first
+    pub fn target_roots(&self) -> Vec<path::PathBuf> {
+        let mut targets: Vec<_> = self.targets.iter().collect();
+        targets.sort_by_key(|&&(_, kind)| kind != TargetKind::Lib);
+        targets.into_iter().map(|&(ref p, _)| p.clone()).collect()
+    }
+
+    /// Checks if the file is the root of a target of the package
+    pub fn is_target_root(&self, filepath: &path::Path) -> bool {
+        self.targets.iter().any(|&(ref p, _)| p == filepath)
+    }
+
+    /// Returns the kind of the target the file is compiled in.
+    /// Files which aren't a target root belong to the target in the nearest directory above,
+    /// which is only a guess for files which aren't declared as a module of any target.
+    pub fn target_kind(&self, filepath: &path::Path) -> TargetKind {
+        if let Some(&(_, kind)) = self.targets.iter().find(|&&(ref p, _)| p == filepath) {
+            return kind;
+        }
+        self.targets
+            .iter()
+            .filter_map(|&(ref p, kind)| {
+                let dir = p.parent()?;
+                if filepath.starts_with(dir) {
+                    Some((dir.components().count(), kind))
+                } else {
+                    None
+                }
+            })
+            // prefer the lib among targets in the same directory, like `src/main.rs`
+            .max_by_key(|&(depth, kind)| (depth, kind == TargetKind::Lib))
+            .map_or(TargetKind::Lib, |(_, kind)| kind)
+    }
+}
+
+/// How the dependencies of packages are resolved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepsResolver {
+    /// With cargo, falling back to reading `Cargo.lock` when cargo fails,
+    /// e.g. without network access
+    Cargo,
+    /// By reading `Cargo.lock`, and the sources already downloaded or vendored,
+    /// without running cargo
+    Offline,
+}
+
+/// The edition of a package. Editions are ordered, so that `>= Edition::Ed2018`
+/// checks for 2018-style paths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edition {
+    Ed2015,
+    Ed2018,
+    /// 2021 and later editions, which resolve paths as 2018 does
+    Ed2021,
+}
+
+/// settings of a package read from its manifest
+#[derive(Clone, Debug)]
+pub struct ManifestInfo {
+    /// names of the features enabled by default
+    pub features: HashSet<String>,
+    pub edition: Edition,
+    /// last modified time of Cargo.toml
+    modified: SystemTime,
 }
 
 /// Used by the FileCache for loading files
@@ -713,9 +978,34 @@ impl FileCache {
             masked_map: RefCell::new(HashMap::new()),
             loader: Box::new(loader),
             deps_map: RefCell::new(HashMap::new()),
+            blanket_impls_map: RefCell::new(HashMap::new()),
+            macro_rules_map: RefCell::new(HashMap::new()),
+            manifest_map: RefCell::new(HashMap::new()),
+            package_manifest_map: RefCell::new(HashMap::new()),
+            toolchain_map: RefCell::new(HashMap::new()),
+            rust_src_layout_map: RefCell::new(HashMap::new()),
+            index_map: RefCell::new(HashMap::new()),
+            index_dir: index::default_index_dir(),
+            deps_resolver: DepsResolver::Cargo,
+            unsaved_files: RefCell::new(HashSet::new()),
+            crate_module_map: RefCell::new(HashMap::new()),
         }
     }
 
+    /// Set the directory the indexes built by `racer index` are read from
+    ///
+    /// It defaults to `$RACER_INDEX_DIR`, or `racer/index` in the cache directory of the user.
+    pub fn set_index_dir<P: Into<path::PathBuf>>(&mut self, dir: P) {
+        self.index_dir = Some(dir.into());
+    }
+
+    /// Set how the dependencies of packages are resolved
+    ///
+    /// It defaults to `DepsResolver::Cargo`.
+    pub fn set_deps_resolver(&mut self, resolver: DepsResolver) {
+        self.deps_resolver = resolver;
+    }
+
     /// Remove specific files from the cache
     ///
     /// Returns true if a file was removed
@@ -723,9 +1013,25 @@ impl FileCache {
         let path = path.as_ref();
         let mut raw = self.raw_map.borrow_mut();
         let mut masked = self.masked_map.borrow_mut();
+        self.blanket_impls_map.borrow_mut().remove(path);
+        self.macro_rules_map.borrow_mut().remove(path);
+        self.index_map.borrow_mut().remove(path);
+        self.unsaved_files.borrow_mut().remove(path);
+        // the file on disk may declare other modules
+        self.remove_crate_modules(path);
         raw.remove(path).is_some() || masked.remove(path).is_some()
     }
 
+    /// Drop the walked module tree of the crate the file is a module of
+    fn remove_crate_modules(&self, path: &path::Path) {
+        let mut modules = self.crate_module_map.borrow_mut();
+        let crate_root = match modules.get(path) {
+            Some(&(ref crate_root, _)) => crate_root.clone(),
+            None => return,
+        };
+        modules.retain(|_, &mut (ref root, _)| *root != crate_root);
+    }
+
     /// Add/Replace a file in both versions.
     fn cache_file_contents<P, T>(&self, filepath: P, buf: T)
     where
@@ -735,9 +1041,21 @@ impl FileCache {
         let pathbuf = filepath.into();
         let src = IndexedSource::new(buf.into());
         let masked_src = IndexedSource::new(scopes::mask_comments(src.as_src()));
+        // most edits keep the module tree, which is only walked again when they don't
+        let module_tree_changed = match self.masked_map.borrow().get(&pathbuf) {
+            Some(old) => module_decls(&old.code) != module_decls(&masked_src.code),
+            None => true,
+        };
+        if module_tree_changed {
+            self.remove_crate_modules(&pathbuf);
+        }
         self.raw_map
             .borrow_mut()
             .insert(pathbuf.clone(), Rc::new(src));
+        self.blanket_impls_map.borrow_mut().remove(&pathbuf);
+        self.macro_rules_map.borrow_mut().remove(&pathbuf);
+        self.index_map.borrow_mut().remove(&pathbuf);
+        self.unsaved_files.borrow_mut().insert(pathbuf.clone());
         self.masked_map
             .borrow_mut()
             .insert(pathbuf, Rc::new(masked_src));
@@ -773,8 +1091,10 @@ impl FileCache {
         msrc
     }
 
-    fn cache_deps(&self, manifest: path::PathBuf, deps: DepsInfo) {
-        self.deps_map.borrow_mut().insert(manifest, Rc::new(deps));
+    fn cache_deps(&self, manifest: path::PathBuf, deps: DepsInfo) -> Rc<DepsInfo> {
+        let deps = Rc::new(deps);
+        self.deps_map.borrow_mut().insert(manifest, deps.clone());
+        deps
     }
 }
 
@@ -798,6 +1118,31 @@ pub struct Session<'c> {
     /// The file cache is used within a session to prevent multiple reads. It is
     /// borrowed here in order to support reuse across Racer operations.
     cache: &'c FileCache,
+
+    /// Configuration `#[cfg(...)]` attributes are evaluated against
+    cfg: Cfg,
+
+    /// Package whose `#[cfg(test)]` items are enabled, since the cursor is in test code
+    test_package: RefCell<Option<path::PathBuf>>,
+
+    /// Glob imports being resolved for a name (file, start of the import, name)
+    glob_imports: RefCell<HashSet<(path::PathBuf, Point, String)>>,
+
+    /// Rust source tree of the toolchain selected by the project the cursor is in,
+    /// used instead of `RUST_SRC_PATH`
+    rust_src_path: RefCell<Option<path::PathBuf>>,
+
+    /// Whether the crate the cursor is in is `#![no_std]`
+    no_std: Cell<bool>,
+
+    /// File the cursor is in, whose crate is `crate` in paths of items
+    crate_file: RefCell<Option<path::PathBuf>>,
+
+    /// module paths of files seen from the cursor (file -> (crate root, module path))
+    module_paths: RefCell<HashMap<path::PathBuf, Option<(path::PathBuf, Vec<String>)>>>,
+
+    /// paths traits are imported by from the cursor ((file, point of the trait) -> path)
+    trait_paths: RefCell<HashMap<(path::PathBuf, Point), String>>,
 }
 
 impl<'c> fmt::Debug for Session<'c> {
@@ -823,7 +1168,153 @@ impl<'c> Session<'c> {
     ///
     /// [`FileCache`]: struct.FileCache.html
     pub fn new(cache: &'c FileCache) -> Session<'c> {
-        Session { cache: cache }
+        Session::with_cfg(cache, Cfg::default())
+    }
+
+    /// Create a Session which evaluates `#[cfg(...)]` attributes against `cfg`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate racer;
+    ///
+    /// let cache = racer::FileCache::default();
+    /// let cfg = racer::Cfg {
+    ///     target_os: "windows".to_owned(),
+    ///     target_family: "windows".to_owned(),
+    ///     features: Some(vec!["serde".to_owned()].into_iter().collect()),
+    /// };
+    /// let session = racer::Session::with_cfg(&cache, cfg);
+    /// ```
+    pub fn with_cfg(cache: &'c FileCache, cfg: Cfg) -> Session<'c> {
+        Session {
+            cache: cache,
+            cfg: cfg,
+            test_package: RefCell::new(None),
+            glob_imports: RefCell::new(HashSet::new()),
+            rust_src_path: RefCell::new(None),
+            no_std: Cell::new(false),
+            crate_file: RefCell::new(None),
+            module_paths: RefCell::new(HashMap::new()),
+            trait_paths: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The configuration `#[cfg(...)]` attributes are evaluated against
+    pub fn cfg(&self) -> &Cfg {
+        &self.cfg
+    }
+
+    /// Enables `#[cfg(test)]` items in the package of the file, or disables them
+    /// everywhere if `None`
+    pub fn set_test_context(&self, filepath: Option<&path::Path>) {
+        *self.test_package.borrow_mut() =
+            filepath.map(|filepath| fileres::get_package_root(filepath, self));
+    }
+
+    /// Checks if `#[cfg(test)]` items are enabled in the file
+    pub fn is_test_context(&self, filepath: &path::Path) -> bool {
+        match *self.test_package.borrow() {
+            Some(ref root) => *root == fileres::get_package_root(filepath, self),
+            None => false,
+        }
+    }
+
+    /// Marks the glob import at the point of the file as being resolved for the name,
+    /// returning false if it already is
+    ///
+    /// With `#[cfg(test)]` modules enabled, `use super::*` can lead back to itself through
+    /// searches which don't carry the pending imports, like the ones of crate roots.
+    pub fn enter_glob_import(&self, filepath: &path::Path, point: Point, name: &str) -> bool {
+        self.glob_imports
+            .borrow_mut()
+            .insert((filepath.to_owned(), point, name.to_owned()))
+    }
+
+    /// Unmarks the glob import marked by `enter_glob_import`
+    pub fn leave_glob_import(&self, filepath: &path::Path, point: Point, name: &str) {
+        self.glob_imports
+            .borrow_mut()
+            .remove(&(filepath.to_owned(), point, name.to_owned()));
+    }
+
+    /// Uses `core` instead of `std` if the crate of the file is `#![no_std]`,
+    /// or `std` if `None`
+    pub fn set_no_std_context(&self, filepath: Option<&path::Path>) {
+        let no_std = filepath.map_or(false, |filepath| nameres::is_no_std_crate(filepath, self));
+        self.no_std.set(no_std);
+    }
+
+    /// Checks if the crate the cursor is in is `#![no_std]`
+    pub fn is_no_std_context(&self) -> bool {
+        self.no_std.get()
+    }
+
+    /// Names the crate of the file `crate` in paths of items, or the crate of each item
+    /// itself if `None`
+    pub fn set_crate_context(&self, filepath: Option<&path::Path>) {
+        *self.crate_file.borrow_mut() = filepath.map(|filepath| filepath.to_owned());
+        self.module_paths.borrow_mut().clear();
+        self.trait_paths.borrow_mut().clear();
+    }
+
+    /// The file whose crate is `crate` in paths of items
+    pub fn crate_context(&self) -> Option<path::PathBuf> {
+        self.crate_file.borrow().clone()
+    }
+
+    /// get the cached module path of a file seen from the cursor if it exists
+    pub fn get_module_path<P: AsRef<path::Path>>(
+        &self,
+        path: P,
+    ) -> Option<Option<(path::PathBuf, Vec<String>)>> {
+        self.module_paths.borrow().get(path.as_ref()).cloned()
+    }
+
+    /// cache the module path of a file seen from the cursor into session
+    pub fn cache_module_path<P: AsRef<path::Path>>(
+        &self,
+        path: P,
+        module_path: Option<(path::PathBuf, Vec<String>)>,
+    ) {
+        self.module_paths
+            .borrow_mut()
+            .insert(path.as_ref().to_owned(), module_path);
+    }
+
+    /// get the cached import path of the trait at the point of the file
+    pub fn get_trait_path<P: AsRef<path::Path>>(&self, path: P, point: Point) -> Option<String> {
+        self.trait_paths
+            .borrow()
+            .get(&(path.as_ref().to_owned(), point))
+            .cloned()
+    }
+
+    /// cache the import path of the trait at the point of the file into session
+    pub fn cache_trait_path<P: AsRef<path::Path>>(
+        &self,
+        path: P,
+        point: Point,
+        trait_path: String,
+    ) {
+        self.trait_paths
+            .borrow_mut()
+            .insert((path.as_ref().to_owned(), point), trait_path);
+    }
+
+    /// Uses the rust source tree of the toolchain selected by a `rust-toolchain` file
+    /// of the project the file belongs to, or `RUST_SRC_PATH` if there is none
+    pub fn set_rust_src_context(&self, filepath: &path::Path) {
+        *self.rust_src_path.borrow_mut() = fileres::get_toolchain_rust_src_path(filepath, self);
+    }
+
+    /// The rust source tree the standard library is searched in,
+    /// or `None` if neither the toolchain of the project nor `RUST_SRC_PATH` has one
+    pub fn rust_src_path(&self) -> Option<path::PathBuf> {
+        match *self.rust_src_path.borrow() {
+            Some(ref srcpath) => Some(srcpath.clone()),
+            None => nameres::RUST_SRC_PATH.clone(),
+        }
     }
 
     /// Specify the contents of a file to be used in completion operations
@@ -876,8 +1367,9 @@ impl<'c> Session<'c> {
     pub fn cache_deps<P: AsRef<path::Path>>(
         &self,
         manifest: P,
-        map: HashMap<String, path::PathBuf>,
-    ) {
+        map: HashMap<String, (path::PathBuf, Vec<DepKind>)>,
+        targets: Vec<(path::PathBuf, TargetKind)>,
+    ) -> Rc<DepsInfo> {
         let manifest = manifest.as_ref();
         let modified = self.cache
             .loader
@@ -885,9 +1377,238 @@ impl<'c> Session<'c> {
             .unwrap_or(SystemTime::now());
         let deps = DepsInfo {
             deps: map,
+            targets: targets,
             modified: modified,
         };
-        self.cache.cache_deps(manifest.to_owned(), deps);
+        self.cache.cache_deps(manifest.to_owned(), deps)
+    }
+
+    /// get cached package settings if they exist
+    pub fn get_manifest_info<P: AsRef<path::Path>>(&self, manifest: P) -> Option<Rc<ManifestInfo>> {
+        let manifest = manifest.as_ref();
+        let infos = self.cache.manifest_map.borrow();
+        let info = infos.get(manifest)?;
+        let modified_correct = self.cache.loader.modified(manifest).unwrap_or(UNIX_EPOCH);
+        if modified_correct > info.modified {
+            None
+        } else {
+            Some(Rc::clone(info))
+        }
+    }
+
+    /// cache package settings into session
+    pub fn cache_manifest_info<P: AsRef<path::Path>>(
+        &self,
+        manifest: P,
+        features: HashSet<String>,
+        edition: Edition,
+    ) -> Rc<ManifestInfo> {
+        let manifest = manifest.as_ref();
+        let modified = self.cache
+            .loader
+            .modified(manifest)
+            .unwrap_or(SystemTime::now());
+        let info = Rc::new(ManifestInfo {
+            features: features,
+            edition: edition,
+            modified: modified,
+        });
+        self.cache
+            .manifest_map
+            .borrow_mut()
+            .insert(manifest.to_owned(), info.clone());
+        info
+    }
+
+    /// get the cached manifest of the package the directory belongs to if it exists
+    pub fn get_package_manifest<P: AsRef<path::Path>>(&self, dir: P) -> Option<path::PathBuf> {
+        let manifests = self.cache.package_manifest_map.borrow();
+        let (ref manifest, modified) = *manifests.get(dir.as_ref())?;
+        let modified_correct = self.cache.loader.modified(manifest).unwrap_or(UNIX_EPOCH);
+        if modified_correct > modified {
+            None
+        } else {
+            Some(manifest.clone())
+        }
+    }
+
+    /// cache the manifest of the package the directories belong to into session
+    pub fn cache_package_manifest(&self, dirs: Vec<path::PathBuf>, manifest: &path::Path) {
+        let modified = self.cache
+            .loader
+            .modified(manifest)
+            .unwrap_or(SystemTime::now());
+        let mut manifests = self.cache.package_manifest_map.borrow_mut();
+        for dir in dirs {
+            manifests.insert(dir, (manifest.to_owned(), modified));
+        }
+    }
+
+    /// get the cached rust source tree of the toolchain file if it exists
+    pub fn get_toolchain_rust_src<P: AsRef<path::Path>>(
+        &self,
+        toolchain_file: P,
+    ) -> Option<path::PathBuf> {
+        let toolchain_file = toolchain_file.as_ref();
+        let toolchains = self.cache.toolchain_map.borrow();
+        let &(ref srcpath, modified) = toolchains.get(toolchain_file)?;
+        let modified_correct = self.cache
+            .loader
+            .modified(toolchain_file)
+            .unwrap_or(UNIX_EPOCH);
+        if modified_correct > modified {
+            None
+        } else {
+            Some(srcpath.clone())
+        }
+    }
+
+    /// cache the rust source tree of the toolchain file into session
+    pub fn cache_toolchain_rust_src<P: AsRef<path::Path>>(
+        &self,
+        toolchain_file: P,
+        srcpath: path::PathBuf,
+    ) -> path::PathBuf {
+        let toolchain_file = toolchain_file.as_ref();
+        let modified = self.cache
+            .loader
+            .modified(toolchain_file)
+            .unwrap_or(SystemTime::now());
+        self.cache
+            .toolchain_map
+            .borrow_mut()
+            .insert(toolchain_file.to_owned(), (srcpath.clone(), modified));
+        srcpath
+    }
+
+    /// Returns the path of a file of the standard library crate in the rust source tree,
+    /// like `libcore/num/mod.rs` or `core/src/num/mod.rs` for `("core", "num/mod.rs")`.
+    /// The layout of each source tree is only checked once.
+    pub fn rust_src_file(
+        &self,
+        srcpath: &path::Path,
+        krate: &str,
+        relpath: &str,
+    ) -> path::PathBuf {
+        let library_layout = *self.cache
+            .rust_src_layout_map
+            .borrow_mut()
+            .entry(srcpath.to_owned())
+            .or_insert_with(|| util::is_library_layout(srcpath));
+        util::rust_src_file(srcpath, library_layout, krate, relpath)
+    }
+
+    /// get the cached index of a file, which is `Some(None)` if the file has no up to date index
+    pub fn get_file_index<P: AsRef<path::Path>>(&self, path: P) -> Option<Option<Rc<FileIndex>>> {
+        self.cache.index_map.borrow().get(path.as_ref()).cloned()
+    }
+
+    /// how the dependencies of packages are resolved
+    pub fn deps_resolver(&self) -> DepsResolver {
+        self.cache.deps_resolver
+    }
+
+    /// the directory indexes of source roots are stored in
+    pub fn index_dir(&self) -> Option<&path::Path> {
+        self.cache.index_dir.as_ref().map(|dir| dir.as_path())
+    }
+
+    /// check if the contents of a file were given by `cache_file_contents`
+    pub fn has_unsaved_contents<P: AsRef<path::Path>>(&self, path: P) -> bool {
+        self.cache.unsaved_files.borrow().contains(path.as_ref())
+    }
+
+    /// cache the index of a file into session
+    pub fn cache_file_index<P: AsRef<path::Path>>(&self, path: P, index: Option<Rc<FileIndex>>) {
+        self.cache
+            .index_map
+            .borrow_mut()
+            .insert(path.as_ref().to_owned(), index);
+    }
+
+    /// get the crate root and module path of a file found in the module tree of a target
+    pub fn get_crate_module<P: AsRef<path::Path>>(
+        &self,
+        path: P,
+    ) -> Option<(path::PathBuf, Vec<String>)> {
+        self.cache
+            .crate_module_map
+            .borrow()
+            .get(path.as_ref())
+            .cloned()
+    }
+
+    /// cache the crate root and module path of a file into session
+    pub fn cache_crate_module<P: AsRef<path::Path>>(
+        &self,
+        path: P,
+        crate_root: path::PathBuf,
+        segments: Vec<String>,
+    ) {
+        self.cache
+            .crate_module_map
+            .borrow_mut()
+            .insert(path.as_ref().to_owned(), (crate_root, segments));
+    }
+
+    /// files of the modules of the crate found by walking its module tree, sorted
+    pub fn crate_module_files<P: AsRef<path::Path>>(&self, crate_root: P) -> Vec<path::PathBuf> {
+        let crate_root = crate_root.as_ref();
+        let mut files: Vec<_> = self.cache
+            .crate_module_map
+            .borrow()
+            .iter()
+            .filter(|&(_, &(ref root, _))| root == crate_root)
+            .map(|(file, _)| file.clone())
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// get cached macros of a file and its modules if they exist
+    pub fn get_macro_rules<P: AsRef<path::Path>>(&self, path: P) -> Option<Rc<Vec<MacroRules>>> {
+        self.cache
+            .macro_rules_map
+            .borrow()
+            .get(path.as_ref())
+            .cloned()
+    }
+
+    /// cache macros of a file and its modules into session
+    pub fn cache_macro_rules<P: AsRef<path::Path>>(
+        &self,
+        path: P,
+        macros: Vec<MacroRules>,
+    ) -> Rc<Vec<MacroRules>> {
+        let macros = Rc::new(macros);
+        self.cache
+            .macro_rules_map
+            .borrow_mut()
+            .insert(path.as_ref().to_owned(), macros.clone());
+        macros
+    }
+
+    /// get cached blanket impls of a file if they exist
+    pub fn get_blanket_impls<P: AsRef<path::Path>>(&self, path: P) -> Option<Rc<Vec<BlanketImpl>>> {
+        self.cache
+            .blanket_impls_map
+            .borrow()
+            .get(path.as_ref())
+            .cloned()
+    }
+
+    /// cache blanket impls of a file into session
+    pub fn cache_blanket_impls<P: AsRef<path::Path>>(
+        &self,
+        path: P,
+        impls: Vec<BlanketImpl>,
+    ) -> Rc<Vec<BlanketImpl>> {
+        let impls = Rc::new(impls);
+        self.cache
+            .blanket_impls_map
+            .borrow_mut()
+            .insert(path.as_ref().to_owned(), impls.clone());
+        impls
     }
 }
 
@@ -963,6 +1684,10 @@ where
 /// Actual implementation without generic bounds
 fn complete_fully_qualified_name_(query: &str, path: &path::Path, session: &Session) -> Vec<Match> {
     let p: Vec<&str> = query.split("::").collect();
+    session.set_test_context(None);
+    session.set_no_std_context(None);
+    session.set_crate_context(None);
+    session.set_rust_src_context(path);
 
     let mut matches = Vec::new();
 
@@ -1030,6 +1755,9 @@ where
 {
     let mut matches = complete_from_file_(filepath.as_ref(), cursor.into(), session);
     matches.dedup_by(|a, b| a.is_same_as(b));
+    if let Some(stddir) = nameres::hidden_std_dir(filepath.as_ref(), session) {
+        matches.retain(|m| !m.filepath.starts_with(&stddir));
+    }
 
     MatchIter {
         matches: matches.into_iter(),
@@ -1037,6 +1765,20 @@ where
     }
 }
 
+/// Enables `#[cfg(test)]` items if the point is in test code, like a `#[cfg(test)]` module
+/// or an integration test under `tests`
+fn update_test_context(filepath: &path::Path, pos: Point, session: &Session) {
+    let src = session.load_file_and_mask_comments(filepath);
+    let is_integration_test = filepath
+        .parent()
+        .map_or(false, |dir| dir == fileres::get_package_root(filepath, session).join("tests"));
+    if is_integration_test || scopes::is_in_test_scope(src.as_src(), pos) {
+        session.set_test_context(Some(filepath));
+    } else {
+        session.set_test_context(None);
+    }
+}
+
 fn complete_from_file_(filepath: &path::Path, cursor: Location, session: &Session) -> Vec<Match> {
     let src = session.load_file_and_mask_comments(filepath);
     let src_text = &src.as_src()[..];
@@ -1049,6 +1791,10 @@ fn complete_from_file_(filepath: &path::Path, cursor: Location, session: &Sessio
             return Vec::new();
         }
     };
+    update_test_context(filepath, pos, session);
+    session.set_rust_src_context(filepath);
+    session.set_no_std_context(Some(filepath));
+    session.set_crate_context(Some(filepath));
 
     let start = scopes::get_start_of_search_expr(src_text, pos);
     let expr = &src_text[start..pos];
@@ -1120,15 +1866,27 @@ fn complete_from_file_(filepath: &path::Path, cursor: Location, session: &Sessio
                 session,
                 &PendingImports::empty(),
             ) {
-                out.push(m);
+                if nameres::is_accessible(&m, filepath, pos, session) {
+                    out.push(m);
+                }
             }
         }
         CompletionType::Field => {
             let context = ast::get_type_of(contextstr.to_owned(), filepath, pos, session);
             debug!("complete_from_file context is {:?}", context);
             context.map(|ty| {
-                complete_field_for_ty(ty, searchstr, SearchType::StartsWith, session, &mut out);
+                complete_field_for_ty(
+                    ty,
+                    searchstr,
+                    SearchType::StartsWith,
+                    filepath,
+                    pos,
+                    session,
+                    &mut out,
+                );
             });
+            out.retain(|m| nameres::is_accessible(m, filepath, pos, session));
+            nameres::flag_traits_out_of_scope(&mut out, filepath, pos, session);
         }
     }
 
@@ -1139,6 +1897,8 @@ fn complete_field_for_ty(
     ty: Ty,
     searchstr: &str,
     stype: SearchType,
+    filepath: &path::Path,
+    pos: Point,
     session: &Session,
     out: &mut Vec<Match>,
 ) {
@@ -1150,11 +1910,55 @@ fn complete_field_for_ty(
                 out.push(m)
             }
         }
-        Ty::RefPtr(m) => complete_field_for_ty(*m, searchstr, stype, session, out),
+        Ty::RefPtr(m) => {
+            complete_field_for_ty(*m, searchstr, stype, filepath, pos, session, out)
+        }
+        Ty::Vec(ty) | Ty::FixedLengthVec(ty, _) => {
+            if let Some(m) = ast::get_slice_match(&ty, session) {
+                complete_field_for_ty(Ty::Match(m), searchstr, stype, filepath, pos, session, out)
+            }
+        }
+        Ty::Tuple(elems) => {
+            for (i, elem) in elems.into_iter().enumerate() {
+                let field = i.to_string();
+                if util::symbol_matches(stype, searchstr, &field) {
+                    // tuples have no definition, so positional fields point to where the
+                    // tuple type is written, or to the type of the element of tuple expressions
+                    let (field_path, field_point, local) = match ty_location(&elem) {
+                        Some(location) => location,
+                        None => (filepath.to_path_buf(), pos, true),
+                    };
+                    out.push(Match {
+                        matchstr: field,
+                        filepath: field_path,
+                        point: field_point,
+                        coords: None,
+                        local: local,
+                        mtype: MatchType::StructField,
+                        contextstr: elem.to_string(),
+                        generic_args: Vec::new(),
+                        generic_types: Vec::new(),
+                        docs: String::new(),
+                        trait_path: None,
+                    });
+                }
+            }
+        }
         _ => {}
     }
 }
 
+/// Returns where the type is written or defined, and whether it's local
+fn ty_location(ty: &Ty) -> Option<(path::PathBuf, Point, bool)> {
+    match *ty {
+        Ty::Match(ref m) => Some((m.filepath.clone(), m.point, m.local)),
+        Ty::PathSearch(_, ref scope) => Some((scope.filepath.clone(), scope.point, true)),
+        Ty::RefPtr(ref ty) | Ty::Vec(ref ty) | Ty::FixedLengthVec(ref ty, _) => ty_location(ty),
+        Ty::Tuple(ref elems) => elems.first().and_then(ty_location),
+        Ty::Unsupported => None,
+    }
+}
+
 /// Find the definition for item at given a file, source, and cursor index
 ///
 /// # Examples
@@ -1207,15 +2011,19 @@ where
     P: AsRef<path::Path>,
     C: Into<Location>,
 {
-    find_definition_(filepath.as_ref(), cursor.into(), session).map(|mut m| {
-        if m.coords.is_none() {
-            let point = m.point;
-            let src = session.load_file(m.filepath.as_path());
-            m.coords = src.point_to_coords(point);
+    let mut m = find_definition_(filepath.as_ref(), cursor.into(), session)?;
+    if let Some(stddir) = nameres::hidden_std_dir(filepath.as_ref(), session) {
+        if m.filepath.starts_with(&stddir) {
+            return None;
         }
+    }
+    if m.coords.is_none() {
+        let point = m.point;
+        let src = session.load_file(m.filepath.as_path());
+        m.coords = src.point_to_coords(point);
+    }
 
-        m
-    })
+    Some(m)
 }
 
 pub fn find_definition_(
@@ -1234,6 +2042,10 @@ pub fn find_definition_(
             return None;
         }
     };
+    update_test_context(filepath, pos, session);
+    session.set_rust_src_context(filepath);
+    session.set_no_std_context(Some(filepath));
+    session.set_crate_context(Some(filepath));
 
     // Make sure `src` is in the cache
     let (start, end) = scopes::expand_search_expr(src, pos);
@@ -1284,7 +2096,11 @@ pub fn find_definition_(
             } else {
                 MatchType::StructField
             };
-            context.and_then(|ty| {
+            context.and_then(|mut ty| {
+                // references are auto-dereferenced, as well as types implementing Deref
+                while let Ty::RefPtr(inner) = ty {
+                    ty = *inner;
+                }
                 // for now, just handle matches
                 if let Ty::Match(m) = ty {
                     nameres::search_for_field_or_method(
@@ -1294,6 +2110,11 @@ pub fn find_definition_(
                         session,
                     ).filter(|m| m.mtype == match_type)
                         .nth(0)
+                        .and_then(|m| {
+                            let mut matches = vec![m];
+                            nameres::flag_traits_out_of_scope(&mut matches, filepath, pos, session);
+                            matches.pop()
+                        })
                 } else {
                     None
                 }
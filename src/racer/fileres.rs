@@ -1,11 +1,18 @@
+use cargo::core::dependency::Kind;
 use cargo::core::Workspace;
 use cargo::ops::{resolve_ws_precisely, Packages};
-use cargo::util::important_paths::find_root_manifest_for_wd;
 use cargo::Config;
-use core::Session;
-use nameres::RUST_SRC_PATH;
-use std::collections::{HashMap, HashSet};
+use core::{DepKind, DepsInfo, DepsResolver, Edition, ManifestInfo, Point, SearchType, Session,
+           SessionExt, Src, TargetKind};
+use manifest::{self, ResolvedDeps};
+use matchers;
+use scopes;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use util;
 
 /// get crate file from current path & crate name
 pub fn get_crate_file(name: &str, from_path: &Path, session: &Session) -> Option<PathBuf> {
@@ -18,11 +25,10 @@ pub fn get_crate_file(name: &str, from_path: &Path, session: &Session) -> Option
         warn!("get_outer_crates returned None");
     }
 
-    let srcpath = &*RUST_SRC_PATH;
+    let srcpath = &session.rust_src_path()?;
     {
-        // try lib<name>/lib.rs, like in the rust source dir
-        let cratelibname = format!("lib{}", name);
-        let filepath = srcpath.join(cratelibname).join("lib.rs");
+        // try lib<name>/lib.rs, or <name>/src/lib.rs in newer rust source trees
+        let filepath = session.rust_src_file(srcpath, name, "lib.rs");
         if filepath.exists() || session.contains_file(&filepath) {
             return Some(filepath);
         }
@@ -58,69 +64,407 @@ pub fn get_module_file(name: &str, parentdir: &Path, session: &Session) -> Optio
 
 /// try to get outer crates
 fn get_outer_crates(libname: &str, from_path: &Path, session: &Session) -> Option<PathBuf> {
+    debug!(
+        "[get_outer_crates] lib name: {:?}, from_path: {:?}",
+        libname, from_path
+    );
+    let libname_hyphened = libname.replace("_", "-");
+    let deps_info = get_deps_info(from_path, session)?;
+    let target = get_target_kind(from_path, &deps_info, session);
+    let is_test = session.is_test_context(from_path);
+    deps_info
+        .get_src_path(libname, target, is_test)
+        .or_else(|| deps_info.get_src_path(&libname_hyphened, target, is_test))
+}
+
+/// Returns the names of the crates the file can use from the dependencies of its package
+pub fn get_dependency_names(from_path: &Path, session: &Session) -> Vec<String> {
+    get_deps_info(from_path, session).map_or_else(Vec::new, |deps_info| {
+        let target = get_target_kind(from_path, &deps_info, session);
+        deps_info.names(target, session.is_test_context(from_path))
+    })
+}
+
+/// Returns the name and the root file of the dependency of the package of `from_path`,
+/// including its own library, whose sources contain the file
+pub fn get_dependency_of_file(
+    from_path: &Path,
+    filepath: &Path,
+    session: &Session,
+) -> Option<(String, PathBuf)> {
+    get_deps_info(from_path, session)?.dependency_of_file(filepath)
+}
+
+/// Returns the kind of the target the file is a module of
+fn get_target_kind(from_path: &Path, deps_info: &DepsInfo, session: &Session) -> TargetKind {
+    match get_crate_module(from_path, session) {
+        Some((crate_root, _)) => deps_info.target_kind(&crate_root),
+        None => deps_info.target_kind(from_path),
+    }
+}
+
+/// Returns the root of the crate the file is a module of and the path of the module
+/// in the crate, like `(src/lib.rs, ["shapes"])` for `src/shapes.rs`.
+///
+/// The module trees of the targets of the package are walked from their roots, libraries
+/// first, so that modules shared with `src/main.rs` belong to the library. Files which
+/// aren't declared as a module of any target have no crate.
+pub fn get_crate_module(filepath: &Path, session: &Session) -> Option<(PathBuf, Vec<String>)> {
+    if let Some(module) = session.get_crate_module(filepath) {
+        return Some(module);
+    }
+    let deps_info = get_deps_info(filepath, session)?;
+    for root in deps_info.target_roots() {
+        // roots are cached first, so a cached root has already been walked
+        if session.get_crate_module(&root).is_some() {
+            continue;
+        }
+        walk_module_tree(&root, &root, Vec::new(), session);
+        if let Some(module) = session.get_crate_module(filepath) {
+            return Some(module);
+        }
+    }
+    None
+}
+
+/// Returns the files of the modules of the crate whose root is the file, sorted
+pub fn get_crate_files(crate_root: &Path, session: &Session) -> Vec<PathBuf> {
+    if session.get_crate_module(crate_root).is_none() {
+        walk_module_tree(crate_root, crate_root, Vec::new(), session);
+    }
+    session.crate_module_files(crate_root)
+}
+
+/// Returns the root files of the dependencies of the package the file belongs to
+pub fn get_dependency_roots(from_path: &Path, session: &Session) -> Vec<PathBuf> {
+    get_deps_info(from_path, session).map_or_else(Vec::new, |deps_info| deps_info.dep_roots())
+}
+
+/// Caches the module of the file and of all its submodules as modules of the crate
+fn walk_module_tree(crate_root: &Path, filepath: &Path, segments: Vec<String>, session: &Session) {
+    // the same file may be declared twice with `#[path]`
+    if session.get_crate_module(filepath).is_some() {
+        return;
+    }
+    session.cache_crate_module(filepath, crate_root.to_owned(), segments.clone());
+    if !filepath.exists() && !session.contains_file(filepath) {
+        return;
+    }
+    let msrc = session.load_file_and_mask_comments(filepath);
+    walk_module_scope(crate_root, filepath, msrc.as_src(), 0, &segments, session);
+}
+
+fn walk_module_scope(
+    crate_root: &Path,
+    filepath: &Path,
+    msrc: Src,
+    scope_start: Point,
+    segments: &[String],
+    session: &Session,
+) {
+    for (start, end) in msrc.from(scope_start).iter_stmts() {
+        let (start, end) = (scope_start + start, scope_start + end);
+        let m = match matchers::match_mod(
+            msrc,
+            start,
+            end,
+            "",
+            filepath,
+            SearchType::StartsWith,
+            true,
+            session,
+        ) {
+            Some(m) => m,
+            None => continue,
+        };
+        let mut segments = segments.to_vec();
+        segments.push(m.matchstr.clone());
+        if m.filepath == filepath {
+            // submodules of an inline module
+            if let Some(n) = msrc[start..end].find('{') {
+                walk_module_scope(crate_root, filepath, msrc, start + n + 1, &segments, session);
+            }
+        } else {
+            walk_module_tree(crate_root, &m.filepath, segments, session);
+        }
+    }
+}
+
+/// Returns the source directories of the dependencies of the package the file belongs to
+pub fn get_dependency_src_dirs(from_path: &Path, session: &Session) -> Vec<PathBuf> {
+    get_deps_info(from_path, session).map_or_else(Vec::new, |deps_info| deps_info.dep_src_dirs())
+}
+
+/// get dependencies of the package the file belongs to, resolving them if not cached.
+///
+/// They are resolved as `Session::deps_resolver` selects, with cargo by default.
+fn get_deps_info(from_path: &Path, session: &Session) -> Option<Rc<DepsInfo>> {
+    let manifest = find_package_manifest(from_path, session)?;
+
+    if let Some(deps_info) = session.get_deps(&manifest) {
+        debug!("[get_deps_info] cache exists");
+        return Some(deps_info);
+    }
+    debug!("[get_deps_info] cache doesn't exist");
+    let resolved = match session.deps_resolver() {
+        DepsResolver::Offline => manifest::resolve_deps_offline(&manifest),
+        DepsResolver::Cargo => {
+            resolve_deps_with_cargo(&manifest).or_else(|| manifest::resolve_deps_offline(&manifest))
+        }
+    }?;
+    Some(session.cache_deps(manifest, resolved.deps, resolved.targets))
+}
+
+/// Resolves the dependencies of the package of the manifest with cargo
+fn resolve_deps_with_cargo(manifest: &Path) -> Option<ResolvedDeps> {
     macro_rules! cargo_res {
         ($r:expr) => {
             match $r {
                 Ok(val) => val,
                 Err(err) => {
-                    warn!("[get_outer_crates]: {}", err);
+                    warn!("[resolve_deps_with_cargo]: {}", err);
                     return None;
                 }
             }
         };
     }
-    debug!(
-        "[get_outer_crates] lib name: {:?}, from_path: {:?}",
-        libname, from_path
-    );
-    let libname_hyphened = {
-        let tmp_str = libname.to_owned();
-        tmp_str.replace("_", "-")
-    };
-    let manifest = cargo_res!(find_root_manifest_for_wd(from_path));
-
-    if let Some(deps_info) = session.get_deps(&manifest) {
-        debug!("[get_outer_crates] cache exists");
-        if let Some(p) = deps_info.get_src_path(libname) {
-            Some(p)
-        } else if let Some(p) = deps_info.get_src_path(&libname_hyphened) {
-            Some(p)
+    let config = cargo_res!(Config::default());
+    let ws = cargo_res!(Workspace::new(manifest, &config));
+    // the file may be in any member of the workspace, not only the current one
+    let pkg_cur = ws.members().find(|pkg| pkg.manifest_path() == manifest)?;
+    // what we need is only packages in toml file!
+    // so, we cache only those packages, along with the sections they are declared in
+    // and the names they are renamed to with `package = "..."`
+    let mut toml_deps = HashMap::new();
+    for dep in pkg_cur.dependencies() {
+        let kind = match dep.kind() {
+            Kind::Normal => DepKind::Normal,
+            Kind::Development => DepKind::Dev,
+            Kind::Build => DepKind::Build,
+        };
+        let rename = dep.rename().map(|name| name.replace("-", "_"));
+        toml_deps
+            .entry(dep.name())
+            .or_insert_with(Vec::new)
+            .push((rename, kind));
+    }
+    let specs = cargo_res!(Packages::All.into_package_id_specs(&ws));
+    // now we resolve dependncies with 'all_features=true'
+    let (packages, _) = cargo_res!(resolve_ws_precisely(&ws, None, &[], true, false, &specs));
+    let mut deps_map = HashMap::new();
+    let mut targets = Vec::new();
+    for target in pkg_cur.manifest().targets() {
+        let kind = if target.is_lib() {
+            // tests, examples and binaries refer to the library of their own package
+            let src_path = target.src_path().to_owned();
+            let kinds = vec![DepKind::Package];
+            deps_map.insert(target.name().replace("-", "_"), (src_path.clone(), kinds.clone()));
+            deps_map.insert(target.name().to_owned(), (src_path, kinds));
+            TargetKind::Lib
+        } else if target.is_custom_build() {
+            TargetKind::Build
+        } else if target.is_test() {
+            TargetKind::Test
+        } else if target.is_bench() {
+            TargetKind::Bench
+        } else if target.is_example() {
+            TargetKind::Example
         } else {
-            None
+            TargetKind::Bin
+        };
+        targets.push((target.src_path().to_owned(), kind));
+    }
+    for package_id in packages.package_ids() {
+        let pkg = match packages.get(package_id) {
+            Ok(p) => p,
+            Err(_) => continue,
+        };
+        let declared = match toml_deps.get(&pkg.name()) {
+            Some(declared) => declared,
+            None => continue,
+        };
+        let targets = pkg.manifest().targets();
+        let lib_target = match targets.into_iter().find(|target| target.is_lib()) {
+            Some(target) => target,
+            None => continue,
+        };
+        for &(ref rename, kind) in declared {
+            let name = rename.clone().unwrap_or_else(|| lib_target.name().to_owned());
+            deps_map
+                .entry(name)
+                .or_insert_with(|| (lib_target.src_path().to_owned(), Vec::new()))
+                .1
+                .push(kind);
         }
+    }
+    Some(ResolvedDeps {
+        deps: deps_map,
+        targets: targets,
+    })
+}
+
+/// Returns the rust source tree of the toolchain selected by a `rust-toolchain`
+/// or `rust-toolchain.toml` file of the project the file belongs to, if it's installed
+pub fn get_toolchain_rust_src_path(from_path: &Path, session: &Session) -> Option<PathBuf> {
+    let toolchain_file = util::find_toolchain_file(from_path)?;
+    if let Some(srcpath) = session.get_toolchain_rust_src(&toolchain_file) {
+        return Some(srcpath);
+    }
+    let srcpath = util::toolchain_file_rust_src(&toolchain_file)?;
+    debug!("[get_toolchain_rust_src_path] {:?} -> {:?}", toolchain_file, srcpath);
+    Some(session.cache_toolchain_rust_src(&toolchain_file, srcpath))
+}
+
+/// Returns the settings of the package the file belongs to, like its default features
+pub fn get_manifest_info(from_path: &Path, session: &Session) -> Option<Rc<ManifestInfo>> {
+    let manifest = find_package_manifest(from_path, session)?;
+    if let Some(info) = session.get_manifest_info(&manifest) {
+        return Some(info);
+    }
+    let tables = match fs::read_to_string(&manifest) {
+        Ok(src) => manifest::parse_tables(&src),
+        Err(err) => {
+            warn!("[get_manifest_info] failed to read {:?}: {}", manifest, err);
+            Vec::new()
+        }
+    };
+    Some(session.cache_manifest_info(
+        &manifest,
+        manifest::parse_default_features(&tables),
+        manifest::parse_edition(&tables),
+    ))
+}
+
+/// Returns the edition of the package the file belongs to
+pub fn get_edition(from_path: &Path, session: &Session) -> Edition {
+    get_manifest_info(from_path, session).map_or(Edition::Ed2015, |info| info.edition)
+}
+
+/// Returns the manifest of the package the file belongs to.
+/// Virtual manifests of workspaces are skipped, since they have no package.
+///
+/// The manifest found is cached for each directory walked through, so that the manifests
+/// are only read again once it is modified. Directories not in a package aren't cached,
+/// so that a package created later, e.g. by `cargo init`, is found.
+fn find_package_manifest(from_path: &Path, session: &Session) -> Option<PathBuf> {
+    // files in the cache are never directories, so they need no access to the filesystem
+    let mut dir = if !session.contains_file(from_path) && from_path.is_dir() {
+        Some(from_path)
     } else {
-        debug!("[get_outer_crates] cache doesn't exist");
-        let config = cargo_res!(Config::default());
-        let ws = cargo_res!(Workspace::new(&manifest, &config));
-        let pkg_cur = ws.current_opt()?;
-        // what we need is only packages in toml file!
-        // so, we cache only those packages
-        let toml_deps: HashSet<_> = pkg_cur.dependencies().iter().map(|d| d.name()).collect();
-        let specs = cargo_res!(Packages::All.into_package_id_specs(&ws));
-        // now we resolve dependncies with 'all_features=true'
-        let (packages, _) = cargo_res!(resolve_ws_precisely(&ws, None, &[], true, false, &specs));
-        let mut deps_map = HashMap::new();
-        let mut res = None;
-        for package_id in packages.package_ids() {
-            let pkg = match packages.get(package_id) {
-                Ok(p) => p,
-                Err(_) => continue,
-            };
-            if !toml_deps.contains(&pkg.name()) {
-                continue;
-            }
-            let targets = pkg.manifest().targets();
-            let lib_target = targets.into_iter().find(|target| target.is_lib());
-            if let Some(target) = lib_target {
-                let name = target.name();
-                let src_path = target.src_path().to_owned();
-                if name == libname || name == libname_hyphened {
-                    res = Some(src_path.clone());
-                }
-                deps_map.insert(name.to_owned(), src_path);
+        from_path.parent()
+    };
+    let mut walked = Vec::new();
+    let mut manifest = None;
+    while let Some(d) = dir {
+        if let Some(cached) = session.get_package_manifest(d) {
+            manifest = Some(cached);
+            break;
+        }
+        walked.push(d.to_owned());
+        let toml = d.join("Cargo.toml");
+        if let Ok(src) = fs::read_to_string(&toml) {
+            let tables = manifest::parse_tables(&src);
+            if manifest::find_table(&tables, "package").is_some() {
+                manifest = Some(toml);
+                break;
             }
         }
-        session.cache_deps(manifest, deps_map);
-        res
+        dir = d.parent();
+    }
+    if let Some(ref manifest) = manifest {
+        session.cache_package_manifest(walked, manifest);
+    }
+    manifest
+}
+
+/// Returns the directory of the package the file belongs to,
+/// or the file itself when it isn't in a package
+pub fn get_package_root(filepath: &Path, session: &Session) -> PathBuf {
+    match find_package_manifest(filepath, session) {
+        Some(manifest) => manifest
+            .parent()
+            .map_or_else(|| filepath.to_owned(), |dir| dir.to_owned()),
+        None => filepath.to_owned(),
+    }
+}
+
+/// Returns the file of the module declared at the point with a `#[path = "..."]` attribute.
+///
+/// The path is relative to the directory of the file, or for modules in inline modules,
+/// to the directories named after the inline modules. Those start from a directory named
+/// after the file unless it is a `mod.rs` or a crate root.
+pub fn get_module_file_from_path(
+    msrc: Src,
+    point: Point,
+    filepath: &Path,
+    session: &Session,
+) -> Option<PathBuf> {
+    let rawsrc = session.load_file(filepath);
+    let point = msrc.from + point;
+    let path = scopes::get_preceding_attributes(&msrc.src.code, point)
+        .into_iter()
+        .filter_map(|(start, end)| scopes::parse_path_attribute(&rawsrc.code[start..end]))
+        .next()?
+        .to_owned();
+    debug!("found a path attribute, path = |{}|", path);
+
+    let internalpath = scopes::get_local_module_path(msrc.src.as_src(), point);
+    let mut dir = if internalpath.is_empty() {
+        filepath.parent()?.to_path_buf()
+    } else {
+        get_module_dir(filepath, session)
+    };
+    for s in internalpath {
+        dir.push(s);
+    }
+    let modpath = dir.join(path);
+    if modpath.exists() || session.contains_file(&modpath) {
+        Some(modpath)
+    } else {
+        None
+    }
+}
+
+/// Returns the directory the files of submodules declared in the file are in,
+/// like `src/foo/` for `src/foo.rs` or `src/` for `src/lib.rs`
+pub fn get_module_dir(filepath: &Path, session: &Session) -> PathBuf {
+    let parent = filepath.parent().unwrap_or_else(|| Path::new(""));
+    match filepath.file_stem() {
+        Some(stem) if !is_mod_rs(filepath, session) => parent.join(stem),
+        _ => parent.to_path_buf(),
+    }
+}
+
+/// Returns the non-mod.rs file of the module whose submodules are in the directory,
+/// like `src/foo.rs` for `src/foo/`
+pub fn get_dir_module_file(dir: &Path, session: &Session) -> Option<PathBuf> {
+    let name = dir.file_name()?.to_str()?;
+    let filepath = dir.parent()?.join(format!("{}.rs", name));
+    if filepath.exists() || session.contains_file(&filepath) {
+        Some(filepath)
+    } else {
+        None
+    }
+}
+
+/// Checks if the file owns the directory it is in, like `mod.rs` and crate roots.
+/// Crate roots are the roots of the targets of the package, like `tests/foo.rs` or
+/// `build.rs` but not a `src/foo/main.rs` declared as a module. Outside packages they
+/// are guessed from the file name.
+fn is_mod_rs(filepath: &Path, session: &Session) -> bool {
+    if filepath.ends_with("mod.rs") {
+        return true;
+    }
+    if let Some((_, segments)) = session.get_crate_module(filepath) {
+        return segments.is_empty();
+    }
+    if let Some(deps_info) = get_deps_info(filepath, session) {
+        return deps_info.is_target_root(filepath);
     }
+    let is_bin = filepath
+        .parent()
+        .map_or(false, |dir| dir.ends_with("src/bin"));
+    is_bin || ["lib.rs", "main.rs"]
+        .iter()
+        .any(|name| filepath.ends_with(name))
 }
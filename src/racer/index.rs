@@ -0,0 +1,604 @@
+// Persistent index of the items of source files.
+//
+// Scanning big source trees like the standard library is the slowest part of a
+// search, and was done again on every start. `racer index` stores the item table of
+// each file of a source root on disk, so that searches skip the statements whose
+// names can't match without parsing them.
+//
+// Each source root has a directory under the index directory, named after the hash of
+// its canonical path, with one `.idx` file per source file. An index file records the
+// size and modification time of the file it was built from, and the hash of its
+// contents, and is ignored once the file changes.
+
+use ast;
+use core::{Point, SearchType, Session, SessionExt, Src};
+use fileres;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, UNIX_EPOCH};
+use util::{find_ident_end, is_ident_char, symbol_matches};
+
+/// Version of the format of index files, which is the first field of their header
+const FORMAT_VERSION: &str = "racer-index-3";
+
+/// The kind of a top level statement
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ItemKind {
+    Function,
+    Struct,
+    Enum,
+    Trait,
+    Type,
+    Const,
+    Static,
+    Module,
+    Macro,
+    Impl,
+    Use,
+    ExternCrate,
+    Attribute,
+    Other,
+}
+
+const ITEM_KINDS: [(ItemKind, &str); 14] = [
+    (ItemKind::Function, "fn"),
+    (ItemKind::Struct, "struct"),
+    (ItemKind::Enum, "enum"),
+    (ItemKind::Trait, "trait"),
+    (ItemKind::Type, "type"),
+    (ItemKind::Const, "const"),
+    (ItemKind::Static, "static"),
+    (ItemKind::Module, "mod"),
+    (ItemKind::Macro, "macro"),
+    (ItemKind::Impl, "impl"),
+    (ItemKind::Use, "use"),
+    (ItemKind::ExternCrate, "extern_crate"),
+    (ItemKind::Attribute, "attr"),
+    (ItemKind::Other, "other"),
+];
+
+impl ItemKind {
+    fn name(self) -> &'static str {
+        ITEM_KINDS.iter().find(|&&(k, _)| k == self).unwrap().1
+    }
+
+    fn from_name(s: &str) -> Option<ItemKind> {
+        ITEM_KINDS.iter().find(|&&(_, name)| name == s).map(|&(k, _)| k)
+    }
+}
+
+/// A top level statement of a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexedItem {
+    pub start: Point,
+    pub end: Point,
+    pub kind: ItemKind,
+    /// the name of the item, or empty if it has none
+    pub name: String,
+    /// the name of the type an impl is for
+    pub impl_target: Option<String>,
+}
+
+/// Item table of a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileIndex {
+    /// `stable_hash` of the contents of the file the index was built from
+    pub hash: u64,
+    /// length of the contents of the file
+    pub len: usize,
+    /// modification time of the file since the unix epoch, if known
+    pub modified: Option<Duration>,
+    pub items: Vec<IndexedItem>,
+}
+
+/// The 64-bit FNV-1a hash of the text. Unlike the hashers of the standard library,
+/// it doesn't change between builds or platforms, so it names and validates the
+/// files stored in the index directory.
+fn stable_hash(s: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    s.bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Returns the default directory indexes are stored in, which is `$RACER_INDEX_DIR`,
+/// or `racer/index` in the cache directory of the user
+pub fn default_index_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("RACER_INDEX_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let cache_dir = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("racer").join("index"))
+}
+
+fn root_index_dir(index_dir: &Path, root: &Path) -> PathBuf {
+    let root = root.to_string_lossy();
+    index_dir.join(format!("{:016x}", stable_hash(&root)))
+}
+
+fn index_file_path(root_dir: &Path, relpath: &Path) -> PathBuf {
+    let relpath = relpath.to_string_lossy();
+    root_dir.join(format!("{:016x}.idx", stable_hash(&relpath)))
+}
+
+/// Strips keywords which can precede the keyword of an item, like `pub(crate)` or `unsafe`
+fn strip_item_prefixes(mut blob: &str) -> &str {
+    loop {
+        let rest = if blob.starts_with("pub(") {
+            match blob.find(')') {
+                Some(n) => &blob[n + 1..],
+                None => return blob,
+            }
+        } else {
+            let prefixes = ["pub", "unsafe", "default", "async", "extern", "const"];
+            let prefix = prefixes.iter().find(|prefix| {
+                blob.starts_with(*prefix)
+                    && !blob[prefix.len()..].starts_with(|c: char| is_ident_char(c))
+            });
+            match prefix {
+                // `extern crate` is an item of its own
+                Some(&"extern") if blob["extern".len()..].trim_left().starts_with("crate") => {
+                    return blob;
+                }
+                // `const` is only a prefix of `const fn`
+                Some(&"const") => {
+                    let rest = strip_item_prefixes(blob["const".len()..].trim_left());
+                    if leading_ident(rest) != "fn" {
+                        return blob;
+                    }
+                    rest
+                }
+                Some(prefix) => {
+                    let rest = blob[prefix.len()..].trim_left();
+                    // the ABI of `extern "C" fn`
+                    if *prefix == "extern" && rest.starts_with('"') {
+                        match rest[1..].find('"') {
+                            Some(n) => &rest[n + 2..],
+                            None => return blob,
+                        }
+                    } else {
+                        rest
+                    }
+                }
+                None => return blob,
+            }
+        };
+        blob = rest.trim_left();
+    }
+}
+
+/// Returns the identifier the text starts with
+fn leading_ident(s: &str) -> &str {
+    let s = s.trim_left();
+    &s[..find_ident_end(s, 0)]
+}
+
+/// Builds the index entry of the top level statement at `start..end`
+fn index_item(msrc: &str, start: Point, end: Point) -> IndexedItem {
+    let blob = &msrc[start..end];
+    let stripped = strip_item_prefixes(blob);
+    let keywords = [
+        ("fn", ItemKind::Function),
+        ("struct", ItemKind::Struct),
+        ("union", ItemKind::Struct),
+        ("enum", ItemKind::Enum),
+        ("trait", ItemKind::Trait),
+        ("type", ItemKind::Type),
+        ("const", ItemKind::Const),
+        ("static", ItemKind::Static),
+        ("mod", ItemKind::Module),
+        ("impl", ItemKind::Impl),
+        ("use", ItemKind::Use),
+        ("extern", ItemKind::ExternCrate),
+        ("macro_rules!", ItemKind::Macro),
+    ];
+    let (kind, rest) = if blob.starts_with('#') {
+        (ItemKind::Attribute, "")
+    } else {
+        let keyword = keywords.iter().find(|&&(keyword, _)| {
+            stripped.starts_with(keyword)
+                && !stripped[keyword.len()..].starts_with(|c: char| is_ident_char(c))
+        });
+        match keyword {
+            Some(&(keyword, kind)) => (kind, &stripped[keyword.len()..]),
+            None => (ItemKind::Other, ""),
+        }
+    };
+    let name = match kind {
+        ItemKind::Static => {
+            let rest = rest.trim_left();
+            if rest.starts_with("mut") && !rest[3..].starts_with(|c: char| is_ident_char(c)) {
+                leading_ident(&rest[3..])
+            } else {
+                leading_ident(rest)
+            }
+        }
+        // `extern crate foo as bar;` is named `bar`
+        ItemKind::ExternCrate => match rest.rfind(" as ") {
+            Some(n) => leading_ident(&rest[n + 4..]),
+            None => leading_ident(rest.trim_left().trim_left_matches("crate")),
+        },
+        ItemKind::Impl | ItemKind::Use | ItemKind::Attribute | ItemKind::Other => "",
+        _ => leading_ident(rest),
+    };
+    let impl_target = if kind == ItemKind::Impl {
+        // `;` may be in the type of an impl, like `impl Foo for [u8; 4]`
+        let decl_end = blob.find('{').unwrap_or(blob.len());
+        if blob[..decl_end].contains('!') {
+            None
+        } else {
+            let decl = format!("{}{{}}", &blob[..decl_end]);
+            ast::parse_impl(decl)
+                .name_path
+                .and_then(|path| path.segments.last().map(|seg| seg.name.clone()))
+        }
+    } else {
+        None
+    };
+    IndexedItem {
+        start: start,
+        end: end,
+        kind: kind,
+        name: name.to_owned(),
+        impl_target: impl_target,
+    }
+}
+
+/// Builds the index of the file from its source, and the source with comments masked
+pub fn build_file_index(rawsrc: &str, msrc: Src) -> FileIndex {
+    let items = msrc.iter_stmts()
+        .map(|(start, end)| index_item(&msrc, start, end))
+        .collect();
+    FileIndex {
+        hash: stable_hash(rawsrc),
+        len: rawsrc.len(),
+        modified: None,
+        items: items,
+    }
+}
+
+impl FileIndex {
+    /// Serializes the index, as a header line followed by a line for each item.
+    /// Names are identifiers, so the tab-separated fields need no escaping.
+    pub fn to_text(&self) -> String {
+        let modified = self.modified
+            .map_or_else(String::new, |d| format!("{}.{:09}", d.as_secs(), d.subsec_nanos()));
+        let mut text = format!(
+            "{}\t{:x}\t{}\t{}\n",
+            FORMAT_VERSION, self.hash, self.len, modified
+        );
+        for item in &self.items {
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                item.start,
+                item.end,
+                item.kind.name(),
+                item.name,
+                item.impl_target.as_ref().map_or("", |s| s.as_str()),
+            ));
+        }
+        text
+    }
+
+    /// Deserializes the index, returning `None` if it's broken or in another format
+    pub fn from_text(text: &str) -> Option<FileIndex> {
+        let mut lines = text.lines();
+        let mut header = lines.next()?.split('\t');
+        if header.next()? != FORMAT_VERSION {
+            return None;
+        }
+        let hash = u64::from_str_radix(header.next()?, 16).ok()?;
+        let len = header.next()?.parse().ok()?;
+        let modified = match header.next()? {
+            "" => None,
+            modified => {
+                let mut parts = modified.split('.');
+                let secs = parts.next()?.parse().ok()?;
+                let nanos = parts.next()?.parse().ok()?;
+                Some(Duration::new(secs, nanos))
+            }
+        };
+        let mut items = Vec::new();
+        for line in lines {
+            let fields: Vec<_> = line.split('\t').collect();
+            if fields.len() != 5 {
+                return None;
+            }
+            items.push(IndexedItem {
+                start: fields[0].parse().ok()?,
+                end: fields[1].parse().ok()?,
+                kind: ItemKind::from_name(fields[2])?,
+                name: fields[3].to_owned(),
+                impl_target: if fields[4].is_empty() {
+                    None
+                } else {
+                    Some(fields[4].to_owned())
+                },
+            });
+        }
+        Some(FileIndex {
+            hash: hash,
+            len: len,
+            modified: modified,
+            items: items,
+        })
+    }
+}
+
+/// Collects the rust files under the directory, skipping build outputs and hidden directories
+fn collect_rs_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("[collect_rs_files] failed to read {:?}: {}", dir, err);
+            return;
+        }
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                collect_rs_files(&path, files);
+            }
+        } else if name.ends_with(".rs") {
+            files.push(path);
+        }
+    }
+}
+
+/// Returns the modification time of the file since the unix epoch
+fn modified_since_epoch(metadata: &fs::Metadata) -> Option<Duration> {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+}
+
+/// Builds the index of all rust files under the source root and stores it in the
+/// index directory of the session, returning the number of indexed files
+pub fn build_index(root: &Path, session: &Session) -> io::Result<usize> {
+    let index_dir = session.index_dir().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "couldn't find the index directory")
+    })?;
+    // lookups canonicalize the files, so roots are stored by their canonical paths
+    let root = root.canonicalize()?;
+    let root_dir = root_index_dir(index_dir, &root);
+    fs::create_dir_all(&root_dir)?;
+    fs::write(root_dir.join("ROOT"), root.to_string_lossy().as_bytes())?;
+
+    let mut files = Vec::new();
+    collect_rs_files(&root, &mut files);
+    for file in &files {
+        let relpath = match file.strip_prefix(&root) {
+            Ok(relpath) => relpath,
+            Err(_) => continue,
+        };
+        let rawsrc = session.load_file(file);
+        let msrc = session.load_file_and_mask_comments(file);
+        let mut index = build_file_index(&rawsrc.code, msrc.as_src());
+        index.modified = fs::metadata(file)
+            .ok()
+            .and_then(|metadata| modified_since_epoch(&metadata));
+        fs::write(index_file_path(&root_dir, relpath), index.to_text())?;
+    }
+    Ok(files.len())
+}
+
+/// Returns the source roots worth indexing for the project in the directory, which are
+/// the rust source tree of its toolchain and the sources of its dependencies
+pub fn default_index_roots(dir: &Path, session: &Session) -> io::Result<Vec<PathBuf>> {
+    session.set_rust_src_context(dir);
+    let srcpath = session.rust_src_path().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "couldn't find the rust source tree; set RUST_SRC_PATH or pass the roots to index",
+        )
+    })?;
+    let mut roots = vec![srcpath];
+    roots.extend(fileres::get_dependency_src_dirs(dir, session));
+    Ok(roots)
+}
+
+/// Reads the index of the file from the nearest indexed source root above it
+fn read_file_index(filepath: &Path, index_dir: &Path) -> Option<FileIndex> {
+    let filepath = filepath.canonicalize().ok()?;
+    let mut dir = filepath.parent();
+    while let Some(root) = dir {
+        let root_dir = root_index_dir(index_dir, root);
+        if root_dir.is_dir() {
+            let relpath = filepath.strip_prefix(root).ok()?;
+            let text = fs::read_to_string(index_file_path(&root_dir, relpath)).ok()?;
+            return FileIndex::from_text(&text);
+        }
+        dir = root.parent();
+    }
+    None
+}
+
+/// Checks if the index was built from the contents of the file in the session.
+/// Files loaded from disk are checked by their size and modification time, and only
+/// contents given by `Session::cache_file_contents` are hashed, as they may differ
+/// from the file on disk.
+fn is_up_to_date(index: &FileIndex, filepath: &Path, session: &Session) -> bool {
+    if session.has_unsaved_contents(filepath) {
+        let rawsrc = session.load_file(filepath);
+        return index.len == rawsrc.code.len() && index.hash == stable_hash(&rawsrc.code);
+    }
+    match fs::metadata(filepath) {
+        Ok(metadata) => {
+            metadata.len() == index.len as u64
+                && index.modified.is_some()
+                && modified_since_epoch(&metadata) == index.modified
+        }
+        Err(_) => false,
+    }
+}
+
+/// Returns the stored index of the file if it's up to date with the contents in the session
+pub fn get_file_index(filepath: &Path, session: &Session) -> Option<Rc<FileIndex>> {
+    if let Some(index) = session.get_file_index(filepath) {
+        return index;
+    }
+    let index = session
+        .index_dir()
+        .and_then(|index_dir| read_file_index(filepath, index_dir))
+        .and_then(|index| {
+            if is_up_to_date(&index, filepath, session) {
+                Some(Rc::new(index))
+            } else {
+                debug!("[get_file_index] index of {:?} is outdated", filepath);
+                None
+            }
+        });
+    session.cache_file_index(filepath, index.clone());
+    index
+}
+
+/// Returns the index of the file if the source is the whole file
+fn get_index_for_src(src: Src, filepath: &Path, session: &Session) -> Option<Rc<FileIndex>> {
+    if src.from != 0 {
+        return None;
+    }
+    let index = get_file_index(filepath, session)?;
+    if index.len == src.len() {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Returns the top level statements of the file which may match the search string,
+/// if the source is the whole file. Statements without a name of their own, like
+/// imports and macro invocations, and enums whose variants may match, are always
+/// returned, and attributes are returned along with the items they are attached to.
+pub fn indexed_stmts(
+    src: Src,
+    searchstr: &str,
+    search_type: SearchType,
+    filepath: &Path,
+    session: &Session,
+) -> Option<Vec<(Point, Point)>> {
+    let index = get_index_for_src(src, filepath, session)?;
+    let searchstr = searchstr.trim_right_matches('!');
+    let mut stmts = Vec::new();
+    let mut attrs = Vec::new();
+    for item in &index.items {
+        let stmt = (item.start, item.end);
+        let keep = match item.kind {
+            // inner attributes like `#![no_std]` apply to the whole file
+            ItemKind::Attribute if src[item.start..].starts_with("#!") => true,
+            ItemKind::Attribute => {
+                attrs.push(stmt);
+                continue;
+            }
+            ItemKind::Enum => true,
+            _ => item.name.is_empty() || symbol_matches(search_type, searchstr, &item.name),
+        };
+        if keep {
+            stmts.extend(attrs.drain(..));
+            stmts.push(stmt);
+        } else {
+            attrs.clear();
+        }
+    }
+    Some(stmts)
+}
+
+/// Returns the top level impls for the type from the index of the file,
+/// if the source is the whole file
+pub fn indexed_impls(
+    src: Src,
+    searchstr: &str,
+    filepath: &Path,
+    session: &Session,
+) -> Option<Vec<(Point, Point)>> {
+    let index = get_index_for_src(src, filepath, session)?;
+    Some(
+        index
+            .items
+            .iter()
+            .filter(|item| item.kind == ItemKind::Impl)
+            // impls whose type couldn't be parsed are checked by the caller
+            .filter(|item| {
+                item.impl_target
+                    .as_ref()
+                    .map_or(true, |target| target == searchstr)
+            })
+            .map(|item| (item.start, item.end))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::IndexedSource;
+    use scopes;
+
+    fn index_of(src: &str) -> FileIndex {
+        let rawsrc = IndexedSource::new(src.to_owned());
+        let msrc = IndexedSource::new(scopes::mask_comments(rawsrc.as_src()));
+        build_file_index(src, msrc.as_src())
+    }
+
+    #[test]
+    fn indexes_items() {
+        let src = "
+/// Makes a shape
+pub(crate) const fn make_shape() -> Shape { Shape }
+#[derive(Debug)]
+pub struct Shape;
+impl<T> Display for Wrapper<T> {}
+static mut COUNT: usize = 0;
+extern crate shapes as figures;
+use std::fmt::Display;
+";
+        let index = index_of(src);
+        let items: Vec<_> = index
+            .items
+            .iter()
+            .map(|item| (item.kind, item.name.as_str()))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                (ItemKind::Function, "make_shape"),
+                (ItemKind::Attribute, ""),
+                (ItemKind::Struct, "Shape"),
+                (ItemKind::Impl, ""),
+                (ItemKind::Static, "COUNT"),
+                (ItemKind::ExternCrate, "figures"),
+                (ItemKind::Use, ""),
+            ]
+        );
+        assert_eq!(index.items[3].impl_target, Some("Wrapper".to_owned()));
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let index = index_of("/// a\nfn a() {}\nimpl Foo { fn b() {} }\n");
+        assert_eq!(FileIndex::from_text(&index.to_text()), Some(index.clone()));
+        let index = FileIndex {
+            modified: Some(Duration::new(1_500_000_000, 42)),
+            ..index
+        };
+        assert_eq!(FileIndex::from_text(&index.to_text()), Some(index));
+        assert_eq!(FileIndex::from_text("racer-index-0\t0\t0\n"), None);
+    }
+
+    #[test]
+    fn hashes_stably() {
+        assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -13,10 +13,13 @@ extern crate syntax;
 extern crate tempfile;
 
 mod ast;
+mod cfg;
 mod codecleaner;
 mod codeiter;
 mod core;
 mod fileres;
+mod index;
+mod manifest;
 mod matchers;
 mod nameres;
 mod scopes;
@@ -26,11 +29,14 @@ mod testutils;
 mod typeinf;
 mod util;
 
+pub use cfg::Cfg;
 pub use core::{complete_from_file, complete_fully_qualified_name, find_definition, to_coords,
                to_point};
-pub use core::{Coordinate, FileCache, FileLoader, Location, Point, Session, SourceByteRange};
+pub use core::{Coordinate, DepsResolver, FileCache, FileLoader, Location, Point, Session,
+               SourceByteRange};
 pub use core::{Match, MatchType, PathSearch};
+pub use index::{build_index, default_index_roots, get_file_index};
 pub use snippets::snippet_for_match;
 pub use util::expand_ident;
 
-pub use util::{get_rust_src_path, RustSrcPathError};
+pub use util::{get_project_rust_src_path, get_rust_src_path, RustSrcPathError};
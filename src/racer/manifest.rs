@@ -0,0 +1,624 @@
+// Reading of Cargo manifests without running cargo.
+//
+// Only the small subset of TOML used by `Cargo.toml` and `Cargo.lock` is understood:
+// `[table]` and `[[array.of.tables]]` headers, and `key = value` entries whose
+// values are kept as raw text, including arrays spanning several lines.
+//
+// This is enough to resolve dependencies offline from `Cargo.lock`, finding their
+// sources in `vendor/` or the registry and git caches under `$CARGO_HOME`.
+
+use core::{DepKind, Edition, TargetKind};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A table of a manifest, like `[dependencies]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    /// the name in the header, like `dependencies` or `package` for `[[package]]`
+    pub name: String,
+    /// keys and raw values of the entries
+    pub entries: Vec<(String, String)>,
+}
+
+impl Table {
+    /// Returns the raw value of the key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|&&(ref k, _)| k == key)
+            .map(|&(_, ref v)| v.as_str())
+    }
+}
+
+/// Removes a `#` comment at the end of the line, ignoring `#` in strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits the manifest into its tables. Entries before the first header go into
+/// a table with an empty name.
+pub fn parse_tables(src: &str) -> Vec<Table> {
+    let mut tables = vec![Table {
+        name: String::new(),
+        entries: Vec::new(),
+    }];
+    let mut lines = src.lines();
+    while let Some(line) = lines.next() {
+        let line = strip_comment(line).trim();
+        if line.starts_with('[') {
+            let name = line.trim_matches(|c| c == '[' || c == ']').trim();
+            tables.push(Table {
+                name: name.to_owned(),
+                entries: Vec::new(),
+            });
+            continue;
+        }
+        let eq = match line.find('=') {
+            Some(eq) => eq,
+            None => continue,
+        };
+        let key = line[..eq].trim().trim_matches('"').to_owned();
+        let mut value = line[eq + 1..].trim().to_owned();
+        // arrays and inline tables may span several lines
+        while bracket_level(&value) > 0 {
+            match lines.next() {
+                Some(next) => value.push_str(strip_comment(next).trim()),
+                None => break,
+            }
+        }
+        tables.last_mut().unwrap().entries.push((key, value));
+    }
+    tables
+}
+
+fn bracket_level(value: &str) -> isize {
+    let mut level = 0;
+    let mut in_string = false;
+    for c in value.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '[' | '{' if !in_string => level += 1,
+            ']' | '}' if !in_string => level -= 1,
+            _ => {}
+        }
+    }
+    level
+}
+
+/// Parses a string value like `"2018"`
+pub fn parse_string(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Some(value[1..value.len() - 1].to_owned())
+    } else {
+        None
+    }
+}
+
+/// Parses an array of strings like `["std", "alloc"]`
+pub fn parse_string_array(value: &str) -> Vec<String> {
+    let value = value.trim();
+    if !value.starts_with('[') || !value.ends_with(']') {
+        return Vec::new();
+    }
+    value[1..value.len() - 1]
+        .split(',')
+        .filter_map(parse_string)
+        .collect()
+}
+
+/// Returns the table with the name
+pub fn find_table<'a>(tables: &'a [Table], name: &str) -> Option<&'a Table> {
+    tables.iter().find(|t| t.name == name)
+}
+
+/// Returns the edition of the package
+pub fn parse_edition(tables: &[Table]) -> Edition {
+    let edition = find_table(tables, "package")
+        .and_then(|package| package.get("edition"))
+        .and_then(parse_string);
+    match edition.and_then(|s| s.parse::<u32>().ok()) {
+        Some(year) if year >= 2021 => Edition::Ed2021,
+        Some(year) if year >= 2018 => Edition::Ed2018,
+        _ => Edition::Ed2015,
+    }
+}
+
+/// Returns the `default` feature of the package, along with the features
+/// it enables transitively
+pub fn parse_default_features(tables: &[Table]) -> HashSet<String> {
+    let mut table = HashMap::new();
+    if let Some(features) = find_table(tables, "features") {
+        for &(ref name, ref value) in &features.entries {
+            table.insert(name.as_str(), parse_string_array(value));
+        }
+    }
+
+    let mut features = HashSet::new();
+    let mut pending = vec!["default".to_owned()];
+    while let Some(feature) = pending.pop() {
+        // `dep/feature` enables a feature of a dependency
+        if feature.contains('/') || !features.insert(feature.clone()) {
+            continue;
+        }
+        if let Some(enabled) = table.get(feature.as_str()) {
+            pending.extend(enabled.iter().cloned());
+        }
+    }
+    features
+}
+
+/// Parses an inline table like `{ version = "1.0", path = "../foo" }` into its entries
+pub fn parse_inline_table(value: &str) -> Vec<(String, String)> {
+    let value = value.trim();
+    if !value.starts_with('{') || !value.ends_with('}') {
+        return Vec::new();
+    }
+    let inner = &value[1..value.len() - 1];
+    let mut entries = Vec::new();
+    let mut level = 0;
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in inner.char_indices().chain(Some((inner.len(), ','))) {
+        match c {
+            '"' => in_string = !in_string,
+            '[' | '{' if !in_string => level += 1,
+            ']' | '}' if !in_string => level -= 1,
+            ',' if !in_string && level == 0 => {
+                let entry = &inner[start..i];
+                if let Some(eq) = entry.find('=') {
+                    entries.push((
+                        entry[..eq].trim().trim_matches('"').to_owned(),
+                        entry[eq + 1..].trim().to_owned(),
+                    ));
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// Dependencies and targets of a package
+pub struct ResolvedDeps {
+    /// dependencies(libname -> (src_path, kinds))
+    pub deps: HashMap<String, (PathBuf, Vec<DepKind>)>,
+    /// targets of the package(src_path, kind)
+    pub targets: Vec<(PathBuf, TargetKind)>,
+}
+
+/// A dependency declared in a manifest
+#[derive(Clone, Debug, PartialEq)]
+struct DepSpec {
+    /// name of the package
+    package: String,
+    /// version requirement, like `1.0`
+    version: Option<String>,
+    /// directory of a path dependency
+    path: Option<String>,
+    kind: DepKind,
+}
+
+/// Returns the kind of dependencies declared in the table, like `dev-dependencies`
+/// or `target.'cfg(unix)'.dependencies`
+fn dep_kind_of_table(name: &str) -> Option<DepKind> {
+    let name = if name.starts_with("target.") {
+        &name[name.rfind('.').map_or(0, |n| n + 1)..]
+    } else {
+        name
+    };
+    match name {
+        "dependencies" => Some(DepKind::Normal),
+        "dev-dependencies" => Some(DepKind::Dev),
+        "build-dependencies" => Some(DepKind::Build),
+        _ => None,
+    }
+}
+
+fn parse_dep_spec(name: &str, entries: &[(String, String)], kind: DepKind) -> DepSpec {
+    let get = |key: &str| {
+        entries
+            .iter()
+            .find(|&&(ref k, _)| k == key)
+            .and_then(|&(_, ref v)| parse_string(v))
+    };
+    DepSpec {
+        package: get("package").unwrap_or_else(|| name.to_owned()),
+        version: get("version"),
+        path: get("path"),
+        kind: kind,
+    }
+}
+
+/// Collects the dependencies declared in the manifest, by the names used in code
+fn parse_dep_specs(tables: &[Table]) -> Vec<(String, DepSpec)> {
+    let mut specs = Vec::new();
+    for table in tables {
+        if let Some(kind) = dep_kind_of_table(&table.name) {
+            for &(ref name, ref value) in &table.entries {
+                let spec = match parse_string(value) {
+                    Some(version) => DepSpec {
+                        package: name.clone(),
+                        version: Some(version),
+                        path: None,
+                        kind: kind,
+                    },
+                    None => parse_dep_spec(name, &parse_inline_table(value), kind),
+                };
+                specs.push((name.clone(), spec));
+            }
+            continue;
+        }
+        // `[dependencies.foo]`
+        if let Some(dot) = table.name.rfind('.') {
+            if let Some(kind) = dep_kind_of_table(&table.name[..dot]) {
+                let name = table.name[dot + 1..].trim_matches('"');
+                specs.push((name.to_owned(), parse_dep_spec(name, &table.entries, kind)));
+            }
+        }
+    }
+    specs
+}
+
+fn read_tables(manifest: &Path) -> Option<Vec<Table>> {
+    fs::read_to_string(manifest).ok().map(|src| parse_tables(&src))
+}
+
+/// Returns the name and source path of the lib target of the package in the directory
+fn find_lib_target(dir: &Path) -> Option<(String, PathBuf)> {
+    let tables = read_tables(&dir.join("Cargo.toml"))?;
+    let package_name = find_table(&tables, "package")?.get("name").and_then(parse_string)?;
+    let lib = find_table(&tables, "lib");
+    let name = lib.and_then(|lib| lib.get("name"))
+        .and_then(parse_string)
+        .unwrap_or_else(|| package_name.replace("-", "_"));
+    let src_path = match lib.and_then(|lib| lib.get("path")).and_then(parse_string) {
+        Some(path) => dir.join(path),
+        None => dir.join("src").join("lib.rs"),
+    };
+    if src_path.exists() {
+        Some((name, src_path))
+    } else {
+        None
+    }
+}
+
+/// Returns the `.rs` files directly in the directory
+fn rs_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flat_map(|entries| entries)
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "rs"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Collects the targets of the package, following cargo's conventional layout
+/// and the targets declared in the manifest
+fn find_targets(dir: &Path, tables: &[Table]) -> Vec<(PathBuf, TargetKind)> {
+    let mut targets = Vec::new();
+    if let Some((_, src_path)) = find_lib_target(dir) {
+        targets.push((src_path, TargetKind::Lib));
+    }
+    let build = find_table(tables, "package")
+        .and_then(|package| package.get("build"))
+        .and_then(parse_string)
+        .unwrap_or_else(|| "build.rs".to_owned());
+    targets.push((dir.join(build), TargetKind::Build));
+    targets.push((dir.join("src").join("main.rs"), TargetKind::Bin));
+    let conventional = [
+        ("src/bin", TargetKind::Bin),
+        ("tests", TargetKind::Test),
+        ("benches", TargetKind::Bench),
+        ("examples", TargetKind::Example),
+    ];
+    for &(subdir, kind) in &conventional {
+        for path in rs_files(&dir.join(subdir)) {
+            targets.push((path, kind));
+        }
+    }
+    let declared = [
+        ("bin", TargetKind::Bin),
+        ("test", TargetKind::Test),
+        ("bench", TargetKind::Bench),
+        ("example", TargetKind::Example),
+    ];
+    for table in tables {
+        if let Some(&(_, kind)) = declared.iter().find(|&&(name, _)| name == table.name) {
+            if let Some(path) = table.get("path").and_then(parse_string) {
+                targets.push((dir.join(path), kind));
+            }
+        }
+    }
+    targets.retain(|&(ref path, _)| path.exists());
+    targets
+}
+
+/// A package recorded in `Cargo.lock`
+struct LockedPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+}
+
+fn parse_lock_file(lock_file: &Path) -> Vec<LockedPackage> {
+    let tables = read_tables(lock_file).unwrap_or_else(Vec::new);
+    tables
+        .iter()
+        .filter(|table| table.name == "package")
+        .filter_map(|table| {
+            Some(LockedPackage {
+                name: table.get("name").and_then(parse_string)?,
+                version: table.get("version").and_then(parse_string)?,
+                source: table.get("source").and_then(parse_string),
+            })
+        })
+        .collect()
+}
+
+/// Picks the locked version of the package, preferring one with the same major version
+/// as the requirement when several versions are locked
+fn find_locked<'a>(locked: &'a [LockedPackage], spec: &DepSpec) -> Option<&'a LockedPackage> {
+    let mut candidates = locked.iter().filter(|p| p.name == spec.package);
+    let first = candidates.next()?;
+    let major = spec.version.as_ref().and_then(|req| {
+        req.trim_left_matches(|c: char| !c.is_digit(10))
+            .split('.')
+            .next()
+            .map(|s| format!("{}.", s))
+    });
+    match major {
+        Some(major) => Some(
+            Some(first)
+                .into_iter()
+                .chain(candidates)
+                .find(|p| p.version.starts_with(&major))
+                .unwrap_or(first),
+        ),
+        None => Some(first),
+    }
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    if let Some(home) = env::var_os("CARGO_HOME") {
+        return Some(PathBuf::from(home));
+    }
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".cargo"))
+}
+
+/// Returns the subdirectory of the directory, or of its subdirectories, which has
+/// the package with the name, like a crate in a git checkout of a workspace
+fn find_package_dir(dir: &Path, name: &str, depth: usize) -> Option<PathBuf> {
+    let has_package = read_tables(&dir.join("Cargo.toml"))
+        .and_then(|tables| {
+            find_table(&tables, "package")
+                .and_then(|package| package.get("name"))
+                .and_then(parse_string)
+        })
+        .map_or(false, |package_name| package_name == name);
+    if has_package {
+        return Some(dir.to_path_buf());
+    }
+    if depth == 0 {
+        return None;
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir())
+        .filter_map(|path| find_package_dir(&path, name, depth - 1))
+        .next()
+}
+
+/// Finds the directory with the sources of the locked package
+fn find_locked_source(package: &LockedPackage, workspace_root: &Path) -> Option<PathBuf> {
+    let versioned = format!("{}-{}", package.name, package.version);
+    let vendor = workspace_root.join("vendor");
+    for dir in &[vendor.join(&versioned), vendor.join(&package.name)] {
+        if dir.join("Cargo.toml").exists() {
+            return Some(dir.clone());
+        }
+    }
+    let cargo_home = cargo_home()?;
+    let source = package.source.as_ref()?;
+    if source.starts_with("registry+") {
+        // one directory per registry index, like `github.com-1ecc6299db9ec823`
+        return fs::read_dir(cargo_home.join("registry").join("src"))
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|e| e.path().join(&versioned)))
+            .find(|dir| dir.join("Cargo.toml").exists());
+    }
+    if source.starts_with("git+") {
+        // checkouts are in `<repo>-<hash>/<short rev>`
+        let url = source["git+".len()..].split(|c| c == '?' || c == '#').next()?;
+        let repo = url.trim_right_matches('/').rsplit('/').next()?;
+        let repo = repo.trim_right_matches(".git");
+        let rev = source.rsplit('#').next()?;
+        for checkout in fs::read_dir(cargo_home.join("git").join("checkouts")).ok()? {
+            let checkout = match checkout {
+                Ok(checkout) => checkout.path(),
+                Err(_) => continue,
+            };
+            let is_repo = checkout
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with(&format!("{}-", repo)));
+            if !is_repo {
+                continue;
+            }
+            for revdir in fs::read_dir(&checkout).ok()? {
+                let revdir = match revdir {
+                    Ok(revdir) => revdir.path(),
+                    Err(_) => continue,
+                };
+                let is_rev = revdir
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map_or(false, |name| rev.starts_with(name));
+                if is_rev {
+                    if let Some(dir) = find_package_dir(&revdir, &package.name, 2) {
+                        return Some(dir);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Returns the nearest directory above the manifest with a `Cargo.lock`
+fn find_workspace_root(manifest: &Path) -> Option<PathBuf> {
+    let mut dir = manifest.parent();
+    while let Some(d) = dir {
+        if d.join("Cargo.lock").exists() {
+            return Some(d.to_path_buf());
+        }
+        dir = d.parent();
+    }
+    None
+}
+
+/// Resolves the dependencies of the package of the manifest from its `Cargo.toml`
+/// and `Cargo.lock`, without running cargo
+pub fn resolve_deps_offline(manifest: &Path) -> Option<ResolvedDeps> {
+    let dir = manifest.parent()?;
+    let tables = read_tables(manifest)?;
+    let workspace_root = find_workspace_root(manifest).unwrap_or_else(|| dir.to_path_buf());
+    let locked = parse_lock_file(&workspace_root.join("Cargo.lock"));
+
+    let mut deps: HashMap<String, (PathBuf, Vec<DepKind>)> = HashMap::new();
+    if let Some((name, src_path)) = find_lib_target(dir) {
+        deps.insert(name, (src_path, vec![DepKind::Package]));
+    }
+    for (dep_name, spec) in parse_dep_specs(&tables) {
+        let package_dir = match spec.path {
+            Some(ref path) => Some(dir.join(path)),
+            None => find_locked(&locked, &spec)
+                .and_then(|package| find_locked_source(package, &workspace_root)),
+        };
+        let (lib_name, src_path) = match package_dir.and_then(|d| find_lib_target(&d)) {
+            Some(lib) => lib,
+            None => {
+                debug!("[resolve_deps_offline] couldn't find {:?}", spec);
+                continue;
+            }
+        };
+        // a dependency renamed with `package = "..."` is used by its key
+        let name = if spec.package != dep_name {
+            dep_name.replace("-", "_")
+        } else {
+            lib_name
+        };
+        deps.entry(name)
+            .or_insert_with(|| (src_path, Vec::new()))
+            .1
+            .push(spec.kind);
+    }
+    Some(ResolvedDeps {
+        deps: deps,
+        targets: find_targets(dir, &tables),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[package]
+name = "foo"
+edition = "2018" # the new one
+
+[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+
+[features]
+default = ["std", "serde/derive"]
+std = [
+    "alloc",  # std needs alloc
+]
+alloc = []
+nightly = []
+"#;
+
+    #[test]
+    fn parses_tables() {
+        let tables = parse_tables(MANIFEST);
+        let deps = find_table(&tables, "dependencies").unwrap();
+        assert_eq!(
+            deps.get("serde"),
+            Some(r#"{ version = "1.0", features = ["derive"] }"#)
+        );
+        assert_eq!(parse_edition(&tables), Edition::Ed2018);
+    }
+
+    #[test]
+    fn parses_later_editions() {
+        let edition = |year: &str| {
+            parse_edition(&parse_tables(&format!("[package]\nedition = \"{}\"\n", year)))
+        };
+        assert_eq!(edition("2015"), Edition::Ed2015);
+        assert_eq!(edition("2021"), Edition::Ed2021);
+        assert_eq!(edition("2024"), Edition::Ed2021);
+        assert!(edition("2024") >= Edition::Ed2018);
+    }
+
+    #[test]
+    fn parses_dependency_specs() {
+        let manifest = r#"
+[dependencies]
+log = "0.4"
+shapes = { path = "../shapes", version = "0.1" }
+
+[target.'cfg(unix)'.dev-dependencies]
+tempfile = { version = "3.0" }
+
+[build-dependencies.cc]
+version = "1.0"
+"#;
+        let specs = parse_dep_specs(&parse_tables(manifest));
+        let names: Vec<_> = specs.iter().map(|&(ref name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["log", "shapes", "tempfile", "cc"]);
+        assert_eq!(specs[1].1.path, Some("../shapes".to_owned()));
+        assert_eq!(specs[2].1.kind, DepKind::Dev);
+        assert_eq!(specs[3].1.kind, DepKind::Build);
+        assert_eq!(specs[3].1.version, Some("1.0".to_owned()));
+    }
+
+    #[test]
+    fn parses_inline_tables() {
+        assert_eq!(
+            parse_inline_table(r#"{ version = "1.0", features = ["a", "b"], path = "x" }"#),
+            vec![
+                ("version".to_owned(), r#""1.0""#.to_owned()),
+                ("features".to_owned(), r#"["a", "b"]"#.to_owned()),
+                ("path".to_owned(), r#""x""#.to_owned()),
+            ]
+        );
+        assert!(parse_inline_table(r#""1.0""#).is_empty());
+    }
+
+    #[test]
+    fn parses_default_features() {
+        let tables = parse_tables(MANIFEST);
+        let features = parse_default_features(&tables);
+        let mut features: Vec<_> = features.iter().map(|s| s.as_str()).collect();
+        features.sort();
+        assert_eq!(features, vec!["alloc", "default", "std"]);
+    }
+}
@@ -3,7 +3,7 @@ use core::MatchType::{self, Const, Enum, EnumVariant, For, Function, IfLet, Let,
 use core::Namespace;
 use core::SearchType::{self, ExactMatch, StartsWith};
 use core::{Coordinate, Match, PathSegment, Point, Session, SessionExt, Src};
-use fileres::{get_crate_file, get_module_file};
+use fileres::{get_crate_file, get_module_dir, get_module_file, get_module_file_from_path};
 use nameres::resolve_path;
 use std::path::Path;
 use std::{iter, option, str, vec};
@@ -297,6 +297,7 @@ fn match_pattern_start(
                 generic_args: Vec::new(),
                 generic_types: Vec::new(),
                 docs: String::new(),
+                trait_path: None,
             });
         }
     }
@@ -380,6 +381,7 @@ fn match_pattern_let(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: String::new(),
+                    trait_path: None,
                 });
                 if let ExactMatch = search_type {
                     break;
@@ -487,6 +489,7 @@ pub fn match_for(
                 generic_args: Vec::new(),
                 generic_types: Vec::new(),
                 docs: String::new(),
+                trait_path: None,
             });
         }
     }
@@ -526,11 +529,8 @@ pub fn match_extern_crate(
         blob = &blob[start..];
     }
 
-    if txt_matches(search_type, &format!("extern crate {}", searchstr), blob)
-        && !(txt_matches(search_type, &format!("extern crate {} as", searchstr), blob))
-        || (blob.starts_with("extern crate")
-            && txt_matches(search_type, &format!("as {}", searchstr), blob))
-    {
+    // `extern crate foo as bar;` is only found by its alias `bar`
+    if blob.starts_with("extern crate") && blob.contains(searchstr) {
         debug!("found an extern crate: |{}|", blob);
 
         let extern_crate = if blob.contains('\"') {
@@ -548,6 +548,9 @@ pub fn match_extern_crate(
 
         if let Some(ref name) = extern_crate.name {
             debug!("extern crate {}", name);
+            if !symbol_matches(search_type, searchstr, name) {
+                return None;
+            }
 
             let realname = extern_crate.realname.as_ref().unwrap_or(name);
             get_crate_file(realname, filepath, session).map(|cratepath| {
@@ -563,6 +566,7 @@ pub fn match_extern_crate(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: find_mod_doc(&crate_src, 0),
+                    trait_path: None,
                 });
             });
         }
@@ -601,14 +605,13 @@ pub fn match_mod(
                 generic_args: Vec::new(),
                 generic_types: Vec::new(),
                 docs: String::new(),
+                trait_path: None,
             });
         } else {
             debug!("found a module declaration: |{}|", blob);
 
             // get module from path attribute
-            if let Some(modpath) =
-                scopes::get_module_file_from_path(msrc, blobstart, filepath.parent().unwrap())
-            {
+            if let Some(modpath) = get_module_file_from_path(msrc, blobstart, filepath, session) {
                 let msrc = session.load_file(&modpath);
 
                 return Some(Match {
@@ -622,6 +625,7 @@ pub fn match_mod(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: find_mod_doc(&msrc, 0),
+                    trait_path: None,
                 });
             }
             // get internal module nesting
@@ -629,11 +633,23 @@ pub fn match_mod(
             // because if it is then we need to search further down the
             // directory hierarchy - e.g. <cwd>/foo/bar.rs
             let internalpath = scopes::get_local_module_path(msrc, blobstart);
-            let mut searchdir = filepath.parent().unwrap().to_path_buf();
-            for s in internalpath {
-                searchdir.push(&s);
-            }
-            if let Some(modpath) = get_module_file(l, &searchdir, session) {
+            // submodules of a non-mod.rs file like `src/foo.rs` live in `src/foo/`,
+            // but older code may still have them next to the file
+            let mut searchdirs = vec![
+                get_module_dir(filepath, session),
+                filepath.parent().unwrap().to_path_buf(),
+            ];
+            searchdirs.dedup();
+            let modpath = searchdirs
+                .into_iter()
+                .filter_map(|mut searchdir| {
+                    for s in &internalpath {
+                        searchdir.push(s);
+                    }
+                    get_module_file(l, &searchdir, session)
+                })
+                .next();
+            if let Some(modpath) = modpath {
                 let msrc = session.load_file(&modpath);
                 let context = modpath.to_str().unwrap().to_owned();
                 return Some(Match {
@@ -647,6 +663,7 @@ pub fn match_mod(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: find_mod_doc(&msrc, 0),
+                    trait_path: None,
                 });
             }
         }
@@ -694,6 +711,7 @@ pub fn match_struct(
             generic_args: generics_list.get_idents(),
             generic_types: Vec::new(),
             docs: find_doc(msrc, blobstart + start),
+            trait_path: None,
         })
     } else {
         None
@@ -727,6 +745,7 @@ pub fn match_type(
             generic_args: Vec::new(),
             generic_types: Vec::new(),
             docs: find_doc(msrc, blobstart + start),
+            trait_path: None,
         })
     } else {
         None
@@ -760,6 +779,7 @@ pub fn match_trait(
             generic_args: Vec::new(),
             generic_types: Vec::new(),
             docs: find_doc(msrc, blobstart + start),
+            trait_path: None,
         })
     } else {
         None
@@ -796,6 +816,7 @@ pub fn match_enum_variants(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: find_doc(msrc, blobstart + offset),
+                    trait_path: None,
                 };
                 out.push(m);
             }
@@ -837,6 +858,7 @@ pub fn match_enum(
             generic_args: generics_list.get_idents(),
             generic_types: Vec::new(),
             docs: find_doc(msrc, blobstart + start),
+            trait_path: None,
         })
     } else {
         None
@@ -937,6 +959,10 @@ pub fn match_use(
                 }
             }
             ast::PathAliasKind::Glob => {
+                if !session.enter_glob_import(filepath, blobstart, searchstr) {
+                    debug!("glob import {} involved in a cycle; ignoring", blob);
+                    continue;
+                }
                 let mut search_path = path_alias.path;
                 search_path.segments.push(PathSegment {
                     name: searchstr.to_owned(),
@@ -951,6 +977,7 @@ pub fn match_use(
                     session,
                     pending_imports,
                 );
+                session.leave_glob_import(filepath, blobstart, searchstr);
                 if search_type == StartsWith {
                     return path_iter.collect();
                 }
@@ -1002,6 +1029,7 @@ pub fn match_fn(
                 generic_args: Vec::new(),
                 generic_types: Vec::new(),
                 docs: find_doc(msrc, blobstart + start),
+                trait_path: None,
             })
         } else {
             None
@@ -1041,6 +1069,7 @@ pub fn match_macro(
             generic_args: Vec::new(),
             generic_types: Vec::new(),
             docs: String::new(),
+            trait_path: None,
         })
     } else {
         None
@@ -4,20 +4,23 @@ use core::MatchType::{Builtin, Enum, EnumVariant, FnArg, Function, Impl, MatchAr
                       StructField, Trait, TraitBounds, TraitImpl};
 use core::Namespace;
 use core::SearchType::{self, ExactMatch, StartsWith};
-use core::{Coordinate, Match, Point, Session, SessionExt, Src, Ty};
-use {ast, core, matchers, scopes, typeinf};
+use core::{Coordinate, Edition, MacroRules, Match, Point, Session, SessionExt, Src, Ty};
+use {ast, cfg, core, fileres, index, matchers, scopes, typeinf};
 
-use fileres::{get_crate_file, get_module_file};
+use fileres::{get_crate_file, get_dependency_names, get_dir_module_file, get_edition,
+              get_module_file};
 use matchers::PendingImports;
 use matchers::find_doc;
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::{self, vec};
 use util::{self, calculate_str_hash, closure_valid_arg_scope, find_ident_end, get_rust_src_path,
-           symbol_matches, txt_matches};
+           symbol_matches, txt_matches, Visibility};
 
 lazy_static! {
-    pub static ref RUST_SRC_PATH: PathBuf = get_rust_src_path().unwrap();
+    /// The rust source tree from `RUST_SRC_PATH` or the sysroot, if it can be found
+    pub static ref RUST_SRC_PATH: Option<PathBuf> = get_rust_src_path().ok();
 }
 
 fn search_struct_fields(
@@ -54,6 +57,7 @@ fn search_struct_fields(
                 generic_args: Vec::new(),
                 generic_types: Vec::new(),
                 docs: find_doc(structsrc, field_point),
+                trait_path: None,
             });
         }
     }
@@ -79,6 +83,11 @@ pub fn search_for_impl_methods(
     );
 
     let mut out = Vec::new();
+    let mut impl_traits = Vec::new();
+    // methods of a trait impl are followed by the trait itself, which tags them
+    let mut trait_impl_methods_start = None;
+    // methods on primitives come from lang impls, which don't need imports
+    let tag_traits = match_request.mtype != Builtin;
 
     for m in search_for_impls(
         point,
@@ -90,18 +99,12 @@ pub fn search_for_impl_methods(
         &PendingImports::empty(),
     ) {
         debug!("found impl!! |{:?}| looking for methods", m);
-
-        if m.matchstr == "Deref" {
-            out.extend(search_for_deref_matches(
-                &m,
-                match_request,
-                fieldsearchstr,
-                fpath,
-                session,
-            ));
+        if m.mtype == Trait {
+            impl_traits.push(m.matchstr.clone());
         }
 
         let src = session.load_file(&m.filepath);
+        let methods_start = out.len();
 
         // find the opening brace and skip to it.
         src[m.point..].find('{').map(|n| {
@@ -115,22 +118,60 @@ pub fn search_for_impl_methods(
             ) {
                 out.push(m);
             }
+            if match_request.mtype == Builtin {
+                // impls of primitives are mostly generated by macros like `int_impl!`
+                out.extend(search_scope_for_macro_methods(
+                    point,
+                    src.as_src(),
+                    fieldsearchstr,
+                    &m.filepath,
+                    search_type,
+                    0,
+                    session,
+                ));
+            }
         });
+        match m.mtype {
+            TraitImpl => trait_impl_methods_start = Some(methods_start),
+            Trait if tag_traits => {
+                let start = trait_impl_methods_start.take().unwrap_or(methods_start);
+                let trait_path = get_trait_path(&m, session);
+                for method in &mut out[start..] {
+                    method.trait_path = Some(trait_path.clone());
+                }
+            }
+            _ => trait_impl_methods_start = None,
+        }
         for gen_m in
             search_for_generic_impls(m.point, &m.matchstr, match_request, &m.filepath, session)
         {
             debug!("found generic impl!! {:?}", gen_m);
             let src = session.load_file(&gen_m.filepath);
+            let trait_path = if tag_traits {
+                resolve_path(
+                    &core::Path::from_vec(false, vec![&gen_m.matchstr]),
+                    &gen_m.filepath,
+                    gen_m.point,
+                    ExactMatch,
+                    Namespace::Type,
+                    session,
+                    &PendingImports::empty(),
+                ).nth(0)
+                    .map(|tr| get_trait_path(&tr, session))
+            } else {
+                None
+            };
             // find the opening brace and skip to it.
             src[gen_m.point..].find('{').map(|n| {
                 let point = gen_m.point + n + 1;
-                for gen_method in search_generic_impl_scope_for_methods(
+                for mut gen_method in search_generic_impl_scope_for_methods(
                     point,
                     src.as_src(),
                     fieldsearchstr,
                     &gen_m,
                     search_type,
                 ) {
+                    gen_method.trait_path = trait_path.clone();
                     out.push(gen_method);
                 }
             });
@@ -167,6 +208,17 @@ pub fn search_for_impl_methods(
             }
         }
     }
+    if match_request.mtype == Struct || match_request.mtype == Enum {
+        // generic impls in the same scope as the traits are already found above
+        for m in search_for_blanket_impl_methods(&impl_traits, fieldsearchstr, search_type, session)
+        {
+            if !out.iter()
+                .any(|found| found.filepath == m.filepath && found.point == m.point)
+            {
+                out.push(m);
+            }
+        }
+    }
     out.into_iter()
 }
 
@@ -211,6 +263,7 @@ fn search_scope_for_methods(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: find_doc(&scopesrc, blobstart + start),
+                    trait_path: None,
                 };
                 out.push(m);
             }
@@ -219,6 +272,174 @@ fn search_scope_for_methods(
     out.into_iter()
 }
 
+/// Searches methods defined in macros invoked in the scope, like `int_impl! { i32, .. }`.
+/// Macros defined by `macro_rules!` are followed to their definitions, and the others,
+/// like `doc_comment! { .., pub fn f(self) {} }`, are searched inside their arguments.
+fn search_scope_for_macro_methods(
+    point: Point,
+    src: Src,
+    searchstr: &str,
+    filepath: &Path,
+    search_type: SearchType,
+    depth: usize,
+    session: &Session,
+) -> Vec<Match> {
+    // guards against recursive macros
+    const MAX_DEPTH: usize = 3;
+    let mut out = Vec::new();
+    if depth > MAX_DEPTH {
+        return out;
+    }
+    let scopesrc = src.from(point);
+    for (blobstart, blobend) in scopesrc.iter_stmts() {
+        let blob = &scopesrc[blobstart..blobend];
+        let name_end = find_ident_end(blob, 0);
+        if name_end > 0 && blob[name_end..].starts_with('!') {
+            let name = &blob[..name_end];
+            if name == "macro_rules" {
+                continue;
+            }
+            let macros = get_macro_rules(filepath, session);
+            if let Some(mac) = macros.iter().find(|mac| mac.name == name) {
+                let macro_src = session.load_file(&mac.filepath);
+                for &body in &mac.bodies {
+                    for m in search_scope_for_macro_methods(
+                        body,
+                        macro_src.as_src(),
+                        searchstr,
+                        &mac.filepath,
+                        search_type,
+                        depth + 1,
+                        session,
+                    ) {
+                        // rules may define the same methods for different arguments
+                        if !out.iter().any(|prev: &Match| prev.matchstr == m.matchstr) {
+                            out.push(m);
+                        }
+                    }
+                }
+            } else if let Some(args) = blob.find(|c| c == '{' || c == '(') {
+                out.extend(search_scope_for_macro_methods(
+                    point + blobstart + args + 1,
+                    src,
+                    searchstr,
+                    filepath,
+                    search_type,
+                    depth + 1,
+                    session,
+                ));
+            }
+        } else if depth > 0 {
+            // methods in macros can be preceded by macro arguments or attributes
+            let fn_start = match blob.find("fn ") {
+                Some(n) => n,
+                None => continue,
+            };
+            let method = &blob[fn_start..];
+            let signature = match method.find(|c| c == '{' || c == ';') {
+                Some(n) => method[..n].trim_right(),
+                None => continue,
+            };
+            if txt_matches(search_type, &format!("fn {}", searchstr), signature)
+                && typeinf::first_param_is_self(method)
+            {
+                let start = fn_start + 3;
+                let end = find_ident_end(blob, start);
+                out.push(Match {
+                    matchstr: blob[start..end].to_owned(),
+                    filepath: filepath.to_path_buf(),
+                    point: point + blobstart + start,
+                    coords: None,
+                    local: false,
+                    mtype: Function,
+                    contextstr: signature.to_owned(),
+                    generic_args: Vec::new(),
+                    generic_types: Vec::new(),
+                    docs: find_doc(&scopesrc, blobstart + start),
+                    trait_path: None,
+                });
+            }
+        }
+    }
+    out
+}
+
+/// Returns the macros defined by `macro_rules!` in the file, or in one of the modules
+/// declared in it, like `int_impl!` which is defined in `num/int_macros.rs` and invoked
+/// in `num/mod.rs`. They are cached for each file.
+fn get_macro_rules(filepath: &Path, session: &Session) -> Rc<Vec<MacroRules>> {
+    if let Some(macros) = session.get_macro_rules(filepath) {
+        return macros;
+    }
+    let msrc = session.load_file_and_mask_comments(filepath);
+    let mut macros = find_macro_rules_in_src(&msrc, filepath);
+    let src = msrc.as_src();
+    for (start, end) in src.iter_stmts() {
+        let modpath =
+            match matchers::match_mod(src, start, end, "", filepath, StartsWith, true, session) {
+                Some(ref m) if m.filepath != filepath => m.filepath.clone(),
+                _ => continue,
+            };
+        let modsrc = session.load_file_and_mask_comments(&modpath);
+        macros.extend(find_macro_rules_in_src(&modsrc, &modpath));
+    }
+    session.cache_macro_rules(filepath, macros)
+}
+
+/// Returns the macros defined by `macro_rules!` in the source, with the bodies of all
+/// of their rules
+fn find_macro_rules_in_src(src: &str, filepath: &Path) -> Vec<MacroRules> {
+    const PATTERN: &str = "macro_rules!";
+    let mut macros = Vec::new();
+    for (def, _) in src.match_indices(PATTERN) {
+        let after = &src[def + PATTERN.len()..];
+        let name_start = def + PATTERN.len() + (after.len() - after.trim_left().len());
+        let name_end = find_ident_end(src, name_start);
+        if name_end == name_start {
+            continue;
+        }
+        let open = match src[name_end..].find(|c| c == '{' || c == '(') {
+            Some(n) => name_end + n,
+            None => continue,
+        };
+        macros.push(MacroRules {
+            name: src[name_start..name_end].to_owned(),
+            filepath: filepath.to_path_buf(),
+            bodies: find_macro_rule_bodies(src, open + 1),
+        });
+    }
+    macros
+}
+
+/// Returns the points where the bodies of the rules start, like `pub fn f(self)` of
+/// `($t:ty) => { pub fn f(self) {} };`, for the rules from `start` to the end of the macro
+fn find_macro_rule_bodies(src: &str, start: Point) -> Vec<Point> {
+    let mut bodies = Vec::new();
+    let mut depth = 0;
+    let mut after_arrow = false;
+    for (i, c) in src[start..].char_indices() {
+        let i = start + i;
+        match c {
+            '{' | '(' | '[' => {
+                if depth == 0 && after_arrow {
+                    bodies.push(i + 1);
+                }
+                after_arrow = false;
+                depth += 1;
+            }
+            '}' | ')' | ']' => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+            '=' if depth == 0 && src[i..].starts_with("=>") => after_arrow = true,
+            _ => {}
+        }
+    }
+    bodies
+}
+
 fn search_generic_impl_scope_for_methods(
     point: Point,
     src: Src,
@@ -260,6 +481,7 @@ fn search_generic_impl_scope_for_methods(
                     generic_args: contextm.generic_args.clone(), // Attach impl generic args
                     generic_types: contextm.generic_types.clone(), // Attach impl generic types
                     docs: find_doc(&scopesrc, blobstart + start),
+                    trait_path: None,
                 };
                 out.push(m);
             }
@@ -313,6 +535,7 @@ fn search_scope_for_static_trait_fns(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: find_doc(&scopesrc, blobstart + start),
+                    trait_path: None,
                 };
                 out.push(m);
             }
@@ -321,6 +544,12 @@ fn search_scope_for_static_trait_fns(
     out.into_iter()
 }
 
+/// Traits whose impls have an `Output` associated type, like `Index` or `Add`
+const OUTPUT_TRAITS: [&str; 13] = [
+    "Index", "Add", "Sub", "Mul", "Div", "Rem", "Neg", "Not", "BitAnd", "BitOr", "BitXor", "Shl",
+    "Shr",
+];
+
 pub fn search_for_impls(
     pos: Point,
     searchstr: &str,
@@ -340,8 +569,13 @@ pub fn search_for_impls(
     let scope_start = scopes::scope_start(s.as_src(), pos);
     let src = s.from(scope_start);
 
+    // impls at the top level of an indexed file are read from its index
+    let stmts = match index::indexed_impls(src, searchstr, filepath, session) {
+        Some(impls) => impls,
+        None => src.iter_stmts().collect(),
+    };
     let mut out = Vec::new();
-    for (start, end) in src.iter_stmts() {
+    for (start, end) in stmts {
         let blob = &src[start..end];
 
         if blob.starts_with("impl") {
@@ -380,6 +614,7 @@ pub fn search_for_impls(
                                     generic_args: Vec::new(),
                                     generic_types: Vec::new(),
                                     docs: String::new(),
+                                    trait_path: None,
                                 };
                                 out.push(m);
                             }
@@ -416,6 +651,47 @@ pub fn search_for_impls(
                                         m.generic_args = vec![deref_type.to_owned()];
                                     };
                                 };
+                            } else if OUTPUT_TRAITS.contains(&&*m.matchstr) {
+                                // keep the `Output` type, resolved from the impl block.
+                                // only statements of the impl itself are searched, since
+                                // its methods may have items with an `Output` of their own
+                                let impl_block = src.from_to(start + n + 1, end);
+                                let output = impl_block
+                                    .iter_stmts()
+                                    .map(|(start, end)| &impl_block[start..end])
+                                    .find(|stmt| {
+                                        stmt.starts_with("type ")
+                                            && &stmt[5..find_ident_end(stmt, 5)] == "Output"
+                                    });
+
+                                if let Some(output) = output {
+                                    debug!("{} output {} found", m.matchstr, output);
+
+                                    if let Some(path) = ast::parse_type(output.to_owned()).type_ {
+                                        // `Rhs` defaults to `Self`, like in `impl Add for Foo`
+                                        let rhs = trait_path
+                                            .segments
+                                            .last()
+                                            .and_then(|seg| seg.types.first().cloned())
+                                            .unwrap_or_else(|| {
+                                                core::Path::from_vec(false, vec!["Self"])
+                                            });
+                                        m.generic_args =
+                                            vec!["Output".to_owned(), "Rhs".to_owned()];
+                                        m.generic_types = vec![
+                                            core::PathSearch {
+                                                path: path,
+                                                filepath: filepath.to_path_buf(),
+                                                point: scope_start + start,
+                                            },
+                                            core::PathSearch {
+                                                path: rhs,
+                                                filepath: filepath.to_path_buf(),
+                                                point: scope_start + start,
+                                            },
+                                        ];
+                                    }
+                                };
                             }
                         }
 
@@ -496,6 +772,7 @@ pub fn search_for_generic_impls(
                                     generic_args: vec![gen_arg.name().to_owned()],
                                     generic_types: vec![self_pathsearch],
                                     docs: String::new(),
+                                    trait_path: None,
                                 };
                                 debug!("Found a trait! {:?}", m);
                                 out.push(m);
@@ -646,6 +923,7 @@ fn search_scope_headers(
                         generic_args: Vec::new(),
                         generic_types: Vec::new(),
                         docs: String::new(),
+                        trait_path: None,
                     });
                     if let SearchType::ExactMatch = search_type {
                         break;
@@ -747,6 +1025,7 @@ fn search_fn_args(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: String::new(),
+                    trait_path: None,
                 };
                 debug!("search_fn_args matched: {:?}", m);
                 out.push(m);
@@ -764,7 +1043,9 @@ fn test_do_file_search() {
 
     assert!(matches.len() > 1);
 
-    assert!(matches.any(|ma| ma.filepath.ends_with("src/libstd/lib.rs")));
+    assert!(matches.any(|ma| {
+        ma.filepath.ends_with("src/libstd/lib.rs") || ma.filepath.ends_with("std/src/lib.rs")
+    }));
 }
 
 pub fn do_file_search(
@@ -775,9 +1056,13 @@ pub fn do_file_search(
     debug!("do_file_search with search string \"{}\"", searchstr);
     let mut out = Vec::new();
 
-    let srcpath = RUST_SRC_PATH.as_ref();
+    let srcpath = session.rust_src_path();
     debug!("do_file_search srcpath: {:?}", srcpath);
-    let v = &[srcpath, currentdir][..];
+    let mut v = Vec::new();
+    if let Some(ref srcpath) = srcpath {
+        v.push(srcpath.as_path());
+    }
+    v.push(currentdir);
     debug!("do_file_search v: {:?}", v);
     for srcpath in v {
         if let Ok(iter) = std::fs::read_dir(srcpath) {
@@ -801,13 +1086,15 @@ pub fn do_file_search(
                             generic_args: Vec::new(),
                             generic_types: Vec::new(),
                             docs: String::new(),
+                            trait_path: None,
                         };
                         out.push(m);
                     }
                 }
 
                 if fname.starts_with(searchstr) {
-                    for name in &[&format!("{}.rs", fname)[..], "mod.rs", "lib.rs"] {
+                    // `src/lib.rs` is for crates like `library/std` of newer rust source trees
+                    for name in &[&format!("{}.rs", fname)[..], "mod.rs", "lib.rs", "src/lib.rs"] {
                         let filepath = fpath_buf.join(name);
 
                         if filepath.exists() || session.contains_file(&filepath) {
@@ -822,6 +1109,7 @@ pub fn do_file_search(
                                 generic_args: Vec::new(),
                                 generic_types: Vec::new(),
                                 docs: String::new(),
+                                trait_path: None,
                             };
                             out.push(m);
                         }
@@ -841,6 +1129,7 @@ pub fn do_file_search(
                             generic_args: Vec::new(),
                             generic_types: Vec::new(),
                             docs: String::new(),
+                            trait_path: None,
                         };
                         out.push(m);
                     }
@@ -861,32 +1150,29 @@ pub fn search_crate_root(
 ) -> vec::IntoIter<Match> {
     debug!("search_crate_root |{:?}| {:?}", pathseg, modfpath.display());
 
-    let crateroots = find_possible_crate_root_modules(modfpath.parent().unwrap(), session);
+    let crateroot = get_crate_root(modfpath, session);
     let mut out = Vec::new();
-    for crateroot in crateroots {
-        if *modfpath == *crateroot {
-            continue;
-        }
-        debug!(
-            "going to search for {:?} in crateroot {:?}",
-            pathseg,
-            crateroot.display()
-        );
-        for m in resolve_name(
-            pathseg,
-            &crateroot,
-            0,
-            searchtype,
-            namespace,
-            session,
-            pending_imports,
-        ) {
-            out.push(m);
-            if let ExactMatch = searchtype {
-                break;
-            }
+    if *modfpath == *crateroot {
+        return out.into_iter();
+    }
+    debug!(
+        "going to search for {:?} in crateroot {:?}",
+        pathseg,
+        crateroot.display()
+    );
+    for m in resolve_name(
+        pathseg,
+        &crateroot,
+        0,
+        searchtype,
+        namespace,
+        session,
+        pending_imports,
+    ) {
+        out.push(m);
+        if let ExactMatch = searchtype {
+            break;
         }
-        break;
     }
     out.into_iter()
 }
@@ -894,11 +1180,14 @@ pub fn search_crate_root(
 pub fn find_possible_crate_root_modules(currentdir: &Path, session: &Session) -> Vec<PathBuf> {
     let mut res = Vec::new();
 
-    for root in &["lib.rs", "main.rs"] {
-        let filepath = currentdir.join(root);
-        if filepath.exists() || session.contains_file(&filepath) {
-            res.push(filepath);
-            return res; // for now stop at the first match
+    // with 2018 style modules, `src/foo/main.rs` is a submodule of `src/foo.rs`
+    if get_dir_module_file(currentdir, session).is_none() {
+        for root in &["lib.rs", "main.rs"] {
+            let filepath = currentdir.join(root);
+            if filepath.exists() || session.contains_file(&filepath) {
+                res.push(filepath);
+                return res; // for now stop at the first match
+            }
         }
     }
     // recurse up the directory structure
@@ -916,6 +1205,72 @@ pub fn find_possible_crate_root_modules(currentdir: &Path, session: &Session) ->
     res
 }
 
+/// Returns the root of the crate the file belongs to, which is the root of the target
+/// of its package it's a module of, or else a `lib.rs` or `main.rs` in the directories above.
+/// Files which have neither are crate roots themselves.
+pub fn get_crate_root(filepath: &Path, session: &Session) -> PathBuf {
+    if let Some((crateroot, _)) = fileres::get_crate_module(filepath, session) {
+        return crateroot;
+    }
+    filepath
+        .parent()
+        .and_then(|dir| {
+            find_possible_crate_root_modules(dir, session)
+                .into_iter()
+                .next()
+        })
+        .unwrap_or_else(|| filepath.to_path_buf())
+}
+
+/// Checks if the crate the file belongs to is `#![no_std]`
+pub fn is_no_std_crate(filepath: &Path, session: &Session) -> bool {
+    let crateroot = get_crate_root(filepath, session);
+    let msrc = session.load_file_and_mask_comments(&crateroot);
+    let src = msrc.as_src();
+    for (start, end) in src.iter_stmts() {
+        // crate attributes come before any item
+        if !src[start..end].starts_with("#!") {
+            break;
+        }
+        let attr = cfg::attr_text(src, start, end, &crateroot, session);
+        if cfg::is_no_std_attr(&attr, &crateroot, session) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns the directory of `std` in the rust source tree if the crate the cursor is in
+/// is `#![no_std]`, so that items of `std` reached through re-exports are hidden. Crates
+/// which link `std` anyway with `extern crate std;` can use them.
+pub fn hidden_std_dir(filepath: &Path, session: &Session) -> Option<PathBuf> {
+    if !session.is_no_std_context() {
+        return None;
+    }
+    let srcpath = session.rust_src_path()?;
+    let crateroot = get_crate_root(filepath, session);
+    let links_std = {
+        let msrc = session.load_file_and_mask_comments(&crateroot);
+        let src = msrc.as_src();
+        src.iter_stmts().any(|(start, end)| {
+            let blob = &src[start..end];
+            if !blob.starts_with("extern crate") {
+                return false;
+            }
+            let krate = ast::parse_extern_crate(blob.to_owned());
+            krate.realname.or(krate.name).map_or(false, |name| name == "std")
+        })
+    };
+    if links_std {
+        return None;
+    }
+    // `library/std/src` or `libstd`
+    session
+        .rust_src_file(&srcpath, "std", "lib.rs")
+        .parent()
+        .map(Path::to_path_buf)
+}
+
 pub fn search_next_scope(
     mut startpoint: Point,
     pathseg: &core::PathSegment,
@@ -951,6 +1306,18 @@ pub fn search_next_scope(
     )
 }
 
+/// Checks if the statement at `start..end` is a `#[cfg(...)]` attribute which
+/// disables the item following it.
+/// `#[cfg(test)]` is only enabled when the cursor is in test code, since the test
+/// module hierarchy is often incompatible with the non-test hierarchy.
+fn is_disabled_by_cfg(src: Src, start: Point, end: Point, filepath: &Path, session: &Session) -> bool {
+    if !src[start..end].starts_with("#[cfg(") {
+        return false;
+    }
+    let attr = cfg::attr_text(src, start, end, filepath, session);
+    !cfg::is_cfg_enabled(&attr, filepath, session)
+}
+
 pub fn search_scope(
     start: Point,
     point: Point,
@@ -982,24 +1349,37 @@ pub fn search_scope(
     let mut skip_next_block = false;
     let mut delayed_single_imports = Vec::new();
     let mut delayed_glob_imports = Vec::new();
-    let mut codeit = scopesrc.iter_stmts();
+    // statements at the top level of an indexed file are read from its index
+    let indexed = if start == 0 {
+        index::indexed_stmts(src, searchstr, search_type, filepath, session)
+    } else {
+        None
+    };
+    let mut indexed_iter;
+    let mut stmts_iter;
+    let codeit: &mut Iterator<Item = (Point, Point)> = match indexed {
+        Some(stmts) => {
+            indexed_iter = stmts.into_iter();
+            &mut indexed_iter
+        }
+        None => {
+            stmts_iter = scopesrc.iter_stmts();
+            &mut stmts_iter
+        }
+    };
     let mut v = Vec::new();
 
     // collect up to point so we can search backwards for let bindings
     //  (these take precidence over local fn declarations etc..
-    for (blobstart, blobend) in &mut codeit {
-        //  (e.g. #[cfg(test)])
+    for (blobstart, blobend) in &mut *codeit {
+        //  (e.g. #[cfg(windows)])
         if skip_next_block {
-            skip_next_block = false;
+            // other attributes of the skipped item are skipped along with it
+            skip_next_block = scopesrc[blobstart..blobend].starts_with("#[");
             continue;
         }
 
-        let blob = &scopesrc[blobstart..blobend];
-
-        // for now skip stuff that's meant for testing. Often the test
-        // module hierarchy is incompatible with the non-test
-        // hierarchy and we get into recursive loops
-        if blob.starts_with("#[cfg(test)") {
+        if is_disabled_by_cfg(src, start + blobstart, start + blobend, filepath, session) {
             skip_next_block = true;
             continue;
         }
@@ -1038,22 +1418,19 @@ pub fn search_scope(
     let mut codeit = v.into_iter().chain(codeit);
     for (blobstart, blobend) in &mut codeit {
         // sometimes we need to skip blocks of code if the preceeding attribute disables it
-        //  (e.g. #[cfg(test)])
+        //  (e.g. #[cfg(windows)])
         if skip_next_block {
-            skip_next_block = false;
+            skip_next_block = scopesrc[blobstart..blobend].starts_with("#[");
             continue;
         }
 
-        let blob = &scopesrc[blobstart..blobend];
-
-        // for now skip stuff that's meant for testing. Often the test
-        // module hierarchy is incompatible with the non-test
-        // hierarchy and we get into recursive loops
-        if blob.starts_with("#[cfg(test)") {
+        if is_disabled_by_cfg(src, start + blobstart, start + blobend, filepath, session) {
             skip_next_block = true;
             continue;
         }
 
+        let blob = &scopesrc[blobstart..blobend];
+
         let is_an_import = blob.starts_with("use") || blob.starts_with("pub use");
 
         if is_an_import {
@@ -1097,6 +1474,7 @@ pub fn search_scope(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: String::new(),
+                    trait_path: None,
                 });
             });
         }
@@ -1222,6 +1600,7 @@ fn search_closure_args(
                         generic_args: Vec::new(),
                         generic_types: Vec::new(),
                         docs: String::new(),
+                        trait_path: None,
                     };
                     debug!("search_closure_args matched: {:?}", m);
                     out.push(m);
@@ -1354,6 +1733,14 @@ fn search_local_scopes(
                 session,
                 pending_imports,
             ) {
+                // items of outer scopes may already be found through `use super::*`
+                let is_found = out.iter().any(|found: &Match| {
+                    found.point == m.point && found.filepath == m.filepath
+                        && found.matchstr == m.matchstr
+                });
+                if is_found {
+                    continue;
+                }
                 out.push(m);
                 if let ExactMatch = search_type {
                     return out.into_iter();
@@ -1377,6 +1764,41 @@ fn search_local_scopes(
     }
 }
 
+/// Searches dependencies of 2018 edition crates, which can be used without `extern crate`
+fn search_extern_prelude(
+    searchstr: &str,
+    filepath: &Path,
+    search_type: SearchType,
+    session: &Session,
+) -> vec::IntoIter<Match> {
+    let mut out = Vec::new();
+    if get_edition(filepath, session) < Edition::Ed2018 {
+        return out.into_iter();
+    }
+    for name in get_dependency_names(filepath, session) {
+        if !symbol_matches(search_type, searchstr, &name) {
+            continue;
+        }
+        if let Some(cratepath) = get_crate_file(&name, filepath, session) {
+            let context = cratepath.to_str().unwrap().to_owned();
+            out.push(Match {
+                matchstr: name,
+                filepath: cratepath,
+                point: 0,
+                coords: Some(Coordinate { line: 1, column: 1 }),
+                local: false,
+                mtype: Module,
+                contextstr: context,
+                generic_args: Vec::new(),
+                generic_types: Vec::new(),
+                docs: String::new(),
+                trait_path: None,
+            });
+        }
+    }
+    out.into_iter()
+}
+
 pub fn search_prelude_file(
     pathseg: &core::PathSegment,
     search_type: SearchType,
@@ -1390,9 +1812,18 @@ pub fn search_prelude_file(
     );
     let mut out: Vec<Match> = Vec::new();
 
-    // find the prelude file from the search path and scan it
-    let srcpath = &*RUST_SRC_PATH;
-    let filepath = srcpath.join("libstd").join("prelude").join("v1.rs");
+    // find the prelude file from the search path and scan it.
+    // `no_std` crates have the prelude of `core` instead
+    let krate = if session.is_no_std_context() {
+        "core"
+    } else {
+        "std"
+    };
+    let srcpath = match session.rust_src_path() {
+        Some(srcpath) => srcpath,
+        None => return out.into_iter(),
+    };
+    let filepath = session.rust_src_file(&srcpath, krate, "prelude/v1.rs");
     if filepath.exists() || session.contains_file(&filepath) {
         let msrc = session.load_file_and_mask_comments(&filepath);
         let is_local = true;
@@ -1427,31 +1858,11 @@ pub fn resolve_path_with_str(
     let mut out = Vec::new();
 
     // HACK
-    if path.segments.len() == 1 && path.segments[0].name == "str" {
-        debug!("{:?} == {:?}", path.segments[0], "str");
+    if path.segments.len() == 1 && is_primitive(&path.segments[0].name) {
+        debug!("{:?} is a primitive type", path.segments[0]);
 
-        if let Some(module) = resolve_path(
-            &core::Path::from_vec(true, vec!["std", "str"]),
-            filepath,
-            pos,
-            search_type,
-            namespace,
-            session,
-            &PendingImports::empty(),
-        ).nth(0)
-        {
-            out.push(Match {
-                matchstr: "str".into(),
-                filepath: module.filepath,
-                point: 0,
-                coords: Some(Coordinate { line: 1, column: 1 }),
-                local: false,
-                mtype: Builtin,
-                contextstr: "str".into(),
-                generic_args: vec![],
-                generic_types: vec![],
-                docs: String::new(),
-            });
+        if let Some(m) = get_primitive_match(&path.segments[0].name, session) {
+            out.push(m);
         }
     } else {
         for m in resolve_path(
@@ -1472,6 +1883,79 @@ pub fn resolve_path_with_str(
     out.into_iter()
 }
 
+/// Primitive types which have methods. Slices and pointers are named like `[T]` or `*const T`.
+const PRIMITIVES: [&str; 20] = [
+    "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32",
+    "f64", "char", "bool", "str", "[T]", "*const T", "*mut T",
+];
+
+pub fn is_primitive(name: &str) -> bool {
+    PRIMITIVES.contains(&name)
+}
+
+/// Returns files in the rust source tree which contain inherent impls of the primitive type.
+/// They are `#[lang = ".."]` impls, or trait impls like `impl StrExt for str`.
+fn primitive_impl_files(name: &str, session: &Session) -> Vec<PathBuf> {
+    let srcpath = match session.rust_src_path() {
+        Some(srcpath) => srcpath,
+        None => return Vec::new(),
+    };
+    let no_std = session.is_no_std_context();
+    let files: &[(&str, &str)] = match name {
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+        | "usize" => &[("core", "num/mod.rs")],
+        "f32" => &[("std", "f32.rs"), ("core", "num/f32.rs")],
+        "f64" => &[("std", "f64.rs"), ("core", "num/f64.rs")],
+        "char" => &[("core", "char/methods.rs")],
+        "bool" => &[("core", "bool.rs")],
+        "str" => &[("alloc", "str.rs"), ("core", "str/mod.rs")],
+        "[T]" => &[("alloc", "slice.rs"), ("core", "slice/mod.rs")],
+        // `ptr` became a directory in newer rust source trees, which later moved
+        // the impls of pointers to files of their own
+        "*const T" => &[("core", "ptr/const_ptr.rs"), ("core", "ptr/mod.rs"), ("core", "ptr.rs")],
+        "*mut T" => &[("core", "ptr/mut_ptr.rs"), ("core", "ptr/mod.rs"), ("core", "ptr.rs")],
+        _ => &[],
+    };
+    files
+        .iter()
+        // methods of `std` like `f32::sqrt` aren't available in `no_std` crates
+        .filter(|&&(krate, _)| !(no_std && krate == "std"))
+        .map(|&(krate, file)| session.rust_src_file(&srcpath, krate, file))
+        .filter(|path| path.exists() || session.contains_file(path))
+        .collect()
+}
+
+/// Returns the builtin match for the primitive type, which points to the first file of its impls
+pub fn get_primitive_match(name: &str, session: &Session) -> Option<Match> {
+    let filepath = primitive_impl_files(name, session).into_iter().nth(0)?;
+    let generic_args = if name.ends_with(" T") || name == "[T]" {
+        vec!["T".to_owned()]
+    } else {
+        Vec::new()
+    };
+    Some(Match {
+        matchstr: name.to_owned(),
+        filepath: filepath,
+        point: 0,
+        coords: Some(Coordinate { line: 1, column: 1 }),
+        local: false,
+        mtype: Builtin,
+        contextstr: name.to_owned(),
+        generic_args: generic_args,
+        generic_types: Vec::new(),
+        docs: String::new(),
+        trait_path: None,
+    })
+}
+
+/// Returns the builtin slice type `[T]` whose element type `T` is `elem`.
+pub fn get_slice_match(elem: core::PathSearch, session: &Session) -> Option<Match> {
+    get_primitive_match("[T]", session).map(|mut m| {
+        m.generic_types = vec![elem];
+        m
+    })
+}
+
 #[derive(PartialEq, Debug)]
 pub struct Search {
     path: Vec<String>,
@@ -1515,13 +1999,19 @@ pub fn resolve_name(
         }
     }
 
-    if (is_exact_match && &searchstr[..] == "std")
-        || (!is_exact_match && "std".starts_with(searchstr))
+    // `no_std` crates get `core` instead of `std`
+    let std_crate = if session.is_no_std_context() {
+        "core"
+    } else {
+        "std"
+    };
+    if (is_exact_match && &searchstr[..] == std_crate)
+        || (!is_exact_match && std_crate.starts_with(searchstr))
     {
-        get_crate_file("std", filepath, session).map(|cratepath| {
+        get_crate_file(std_crate, filepath, session).map(|cratepath| {
             let context = cratepath.to_str().unwrap().to_owned();
             out.push(Match {
-                matchstr: "std".into(),
+                matchstr: std_crate.into(),
                 filepath: cratepath,
                 point: 0,
                 coords: Some(Coordinate { line: 1, column: 1 }),
@@ -1531,6 +2021,7 @@ pub fn resolve_name(
                 generic_args: Vec::new(),
                 generic_types: Vec::new(),
                 docs: String::new(),
+                trait_path: None,
             });
         });
 
@@ -1571,6 +2062,13 @@ pub fn resolve_name(
         }
     }
 
+    for m in search_extern_prelude(searchstr, filepath, search_type, session) {
+        out.push(m);
+        if let ExactMatch = search_type {
+            return out.into_iter();
+        }
+    }
+
     for m in search_prelude_file(pathseg, search_type, namespace, session, pending_imports) {
         out.push(m);
         if let ExactMatch = search_type {
@@ -1580,6 +2078,9 @@ pub fn resolve_name(
     // filesearch. Used to complete e.g. extern crate blah or mod foo
     if let StartsWith = search_type {
         for m in do_file_search(searchstr, filepath.parent().unwrap(), session) {
+            if m.matchstr == "std" && session.is_no_std_context() {
+                continue;
+            }
             out.push(m);
         }
     }
@@ -1618,7 +2119,11 @@ pub fn get_super_scope(
                 });
             }
         }
-        None
+        // 2018 style, where the parent of `src/foo/bar.rs` is `src/foo.rs`
+        get_dir_module_file(moduledir, session).map(|f_path| core::Scope {
+            filepath: f_path,
+            point: 0,
+        })
     } else if path.len() == 1 {
         Some(core::Scope {
             filepath: filepath.to_path_buf(),
@@ -1675,6 +2180,22 @@ pub fn resolve_path(
             pending_imports,
         )
     } else if len != 0 {
+        if path.segments[0].name == "crate" {
+            // search from the crate root
+            let mut newpath: core::Path = path.clone();
+            newpath.segments.remove(0);
+            let crateroot = get_crate_root(filepath, session);
+            return resolve_path(
+                &newpath,
+                &crateroot,
+                0,
+                search_type,
+                namespace,
+                session,
+                pending_imports,
+            );
+        }
+
         if path.segments[0].name == "self" {
             // just remove self
             let mut newpath: core::Path = path.clone();
@@ -2052,6 +2573,7 @@ pub fn do_external_search(
                 generic_args: Vec::new(),
                 generic_types: Vec::new(),
                 docs: String::new(),
+                trait_path: None,
             });
         });
     } else {
@@ -2184,11 +2706,25 @@ fn collect_inherited_traits(trait_match: Match, s: &Session) -> Vec<Match> {
     res
 }
 
+/// Searches fields and methods of the type, and the types it auto-derefs to
 pub fn search_for_field_or_method(
     context: Match,
     searchstr: &str,
     search_type: SearchType,
     session: &Session,
+) -> vec::IntoIter<Match> {
+    let mut out = Vec::new();
+    for m in deref_chain(context, session) {
+        out.extend(search_for_field_or_method_(m, searchstr, search_type, session));
+    }
+    out.into_iter()
+}
+
+fn search_for_field_or_method_(
+    context: Match,
+    searchstr: &str,
+    search_type: SearchType,
+    session: &Session,
 ) -> vec::IntoIter<Match> {
     let m = context;
     let mut out = Vec::new();
@@ -2232,17 +2768,29 @@ pub fn search_for_field_or_method(
                 out.push(m);
             }
         }
-        Builtin => for m in search_for_impl_methods(
-            &m,
-            searchstr,
-            m.point,
-            &m.filepath,
-            m.local,
-            search_type,
-            session,
-        ) {
-            out.push(m);
-        },
+        Builtin => {
+            // impls of a primitive type are spread over some files of the rust source tree
+            let mut files = vec![m.filepath.clone()];
+            for file in primitive_impl_files(&m.matchstr, session) {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+            for (i, file) in files.iter().enumerate() {
+                let point = if i == 0 { m.point } else { 0 };
+                for m in search_for_impl_methods(
+                    &m,
+                    searchstr,
+                    point,
+                    file,
+                    m.local,
+                    search_type,
+                    session,
+                ) {
+                    out.push(m);
+                }
+            }
+        }
         Enum => {
             debug!("got an enum, looking for impl methods {}", m.matchstr);
             for m in search_for_impl_methods(
@@ -2278,66 +2826,590 @@ pub fn search_for_field_or_method(
     out.into_iter()
 }
 
-fn search_for_deref_matches(
-    impl_match: &Match,
-    type_match: &Match,
-    fieldsearchstr: &str,
-    fpath: &Path,
+/// Returns the root of the crate of the file and the path of its module, like
+/// `std::io` for `libstd/io/mod.rs`. The crate the cursor is in is named `crate`, and
+/// dependencies are named as the package of the cursor uses them.
+/// Items of `core` and `alloc` are shown as the ones re-exported from `std`,
+/// unless the crate of the cursor is `#![no_std]`.
+fn get_module_path(filepath: &Path, session: &Session) -> Option<(PathBuf, Vec<String>)> {
+    if let Some(module_path) = session.get_module_path(filepath) {
+        return module_path;
+    }
+    let module_path = get_module_path_(filepath, session);
+    session.cache_module_path(filepath, module_path.clone());
+    module_path
+}
+
+fn get_module_path_(filepath: &Path, session: &Session) -> Option<(PathBuf, Vec<String>)> {
+    let rust_src_relpath = session.rust_src_path().and_then(|srcpath| {
+        let relpath = filepath.strip_prefix(&srcpath).ok()?.to_path_buf();
+        Some((srcpath, relpath))
+    });
+    if let Some((srcpath, relpath)) = rust_src_relpath {
+        let mut components = relpath.components();
+        let dirname = components.next()?.as_os_str().to_str()?;
+        let libname = if dirname.starts_with("lib") {
+            dirname.trim_left_matches("lib")
+        } else {
+            // skip `src` of `library/std/src/io/mod.rs`
+            components.next()?;
+            dirname
+        };
+        let crateroot = session.rust_src_file(&srcpath, libname, "lib.rs");
+        let krate = match libname {
+            "core" | "alloc" if !session.is_no_std_context() => "std",
+            krate => krate,
+        };
+        let mut segments = vec![krate.to_owned()];
+        segments.extend(file_module_segments(components.as_path())?);
+        return Some((crateroot, segments));
+    }
+    let cursor_file = session
+        .crate_context()
+        .unwrap_or_else(|| filepath.to_path_buf());
+    if fileres::get_package_root(filepath, session) == fileres::get_package_root(&cursor_file, session)
+    {
+        // a module of a target of the package the cursor is in
+        let crateroot = get_crate_root(filepath, session);
+        let mut segments = if crateroot == get_crate_root(&cursor_file, session) {
+            vec!["crate".to_owned()]
+        } else {
+            // other targets can only use the library of the package
+            let (name, libroot) = fileres::get_dependency_of_file(&cursor_file, filepath, session)?;
+            if libroot != crateroot {
+                return None;
+            }
+            vec![name]
+        };
+        match fileres::get_crate_module(filepath, session) {
+            Some((_, module)) => segments.extend(module),
+            None if *filepath == *crateroot => {}
+            None => {
+                let relpath = filepath.strip_prefix(crateroot.parent()?).ok()?;
+                segments.extend(file_module_segments(relpath)?);
+            }
+        }
+        return Some((crateroot, segments));
+    }
+    let (name, crateroot) = fileres::get_dependency_of_file(&cursor_file, filepath, session)?;
+    let relpath = filepath.strip_prefix(crateroot.parent()?).ok()?;
+    let mut segments = vec![name];
+    segments.extend(file_module_segments(relpath)?);
+    Some((crateroot, segments))
+}
+
+/// Returns the module path of the file relative to the directory of its crate root,
+/// like `["shapes", "circle"]` for `shapes/circle.rs`
+fn file_module_segments(relpath: &Path) -> Option<Vec<String>> {
+    let mut segments = Vec::new();
+    for component in relpath.components() {
+        let name = component.as_os_str().to_str()?;
+        match name {
+            "mod.rs" | "lib.rs" | "main.rs" => {}
+            _ => segments.push(name.trim_right_matches(".rs").to_owned()),
+        }
+    }
+    Some(segments)
+}
+
+/// Returns the path of the trait to import it, or just its name if the path is unknown.
+/// Traits of other crates are named by the shortest path they are exported at, like
+/// `std::iter::Iterator` for the trait defined in `core/src/iter/traits/iterator.rs`.
+fn get_trait_path(trait_match: &Match, session: &Session) -> String {
+    if let Some(trait_path) = session.get_trait_path(&trait_match.filepath, trait_match.point) {
+        return trait_path;
+    }
+    let name = &trait_match.matchstr;
+    let segments = get_module_segments(&trait_match.filepath, trait_match.point, session)
+        .and_then(|(crateroot, segments)| {
+            if segments[0] == "crate" {
+                Some(segments)
+            } else {
+                get_public_module_path(&crateroot, &segments, name, session)
+            }
+        });
+    let trait_path = match segments {
+        Some(segments) => format!("{}::{}", segments.join("::"), name),
+        None => name.clone(),
+    };
+    session.cache_trait_path(&trait_match.filepath, trait_match.point, trait_path.clone());
+    trait_path
+}
+
+/// Returns the shortest path the item named `name` in the module at `segments` of the
+/// crate is public at. It's the first module on the way from the crate root which
+/// re-exports the item with `pub use`, or the module itself if all of them are `pub mod`.
+fn get_public_module_path(
+    crateroot: &Path,
+    segments: &[String],
+    name: &str,
     session: &Session,
-) -> vec::IntoIter<Match> {
-    debug!(
-        "Found a Deref Implementation for {}, Searching for Methods on the Deref Type",
-        type_match.matchstr
-    );
-    let mut out = Vec::new();
+) -> Option<Vec<String>> {
+    let mut filepath = crateroot.to_path_buf();
+    let mut scope_start = 0;
+    for depth in 1..segments.len() {
+        let next_module = &segments[depth];
+        let mut next_scope = None;
+        {
+            let msrc = session.load_file_and_mask_comments(&filepath);
+            let src = msrc.as_src();
+            for (start, end) in src.from(scope_start).iter_stmts() {
+                let (start, end) = (scope_start + start, scope_start + end);
+                let blob = &src[start..end];
+                if is_public_export(blob, name, next_module) {
+                    return Some(segments[..depth].to_vec());
+                }
+                if next_scope.is_some() || !blob.starts_with("pub mod ") {
+                    continue;
+                }
+                let m = matchers::match_mod(
+                    src,
+                    start,
+                    end,
+                    next_module,
+                    &filepath,
+                    ExactMatch,
+                    true,
+                    session,
+                );
+                next_scope = match m {
+                    // an inline module
+                    Some(ref m) if m.filepath == filepath => {
+                        blob.find('{').map(|n| (filepath.clone(), start + n + 1))
+                    }
+                    Some(m) => Some((m.filepath, 0)),
+                    None => None,
+                };
+            }
+        }
+        // the module is private, and the item isn't exported on the way to it
+        let (next_filepath, next_start) = next_scope?;
+        filepath = next_filepath;
+        scope_start = next_start;
+    }
+    Some(segments.to_vec())
+}
 
-    if let Some(type_arg) = impl_match.generic_args.first() {
-        // If Deref to a generic type
-        if let Some(inner_type_path) = generic_arg_to_path(&type_arg, type_match) {
-            let type_match = resolve_path_with_str(
-                &inner_type_path.path,
-                &inner_type_path.filepath,
-                0,
-                SearchType::ExactMatch,
-                Namespace::Type,
-                session,
-            ).nth(0);
-            let subpath = get_subpathsearch(&inner_type_path);
-            if let Some(mut m) = type_match {
-                if let Some(path) = subpath {
-                    m.generic_types.push(path);
+/// Checks if the statement is a `pub use` which exports the name, like
+/// `pub use self::traits::{Iterator, FromIterator};`, or a glob import of the next module
+/// on the way to the item, like `pub use self::traits::*;`
+fn is_public_export(blob: &str, name: &str, next_module: &str) -> bool {
+    if !blob.starts_with("pub use ") {
+        return false;
+    }
+    let idents: Vec<_> = blob.split(|c: char| !util::is_ident_char(c))
+        .filter(|ident| !ident.is_empty())
+        .collect();
+    // `Iterator as Iter` exports it by another name
+    let exports_name = idents
+        .iter()
+        .enumerate()
+        .any(|(i, ident)| *ident == name && idents.get(i + 1) != Some(&"as"));
+    exports_name || (blob.contains('*') && idents.contains(&next_module))
+}
+
+/// Clears `trait_path` of methods whose trait is in scope at the point,
+/// leaving it only on methods which need their trait imported.
+pub fn flag_traits_out_of_scope(
+    matches: &mut [Match],
+    filepath: &Path,
+    pos: Point,
+    session: &Session,
+) {
+    let mut in_scope: Vec<(String, bool)> = Vec::new();
+    for m in matches.iter_mut() {
+        let is_in_scope = match m.trait_path {
+            Some(ref trait_path) => {
+                if let Some(&(_, cached)) = in_scope.iter().find(|&&(ref p, _)| p == trait_path) {
+                    cached
+                } else {
+                    let name = trait_path.rsplit("::").next().unwrap_or(trait_path);
+                    let res = resolve_path(
+                        &core::Path::from_vec(false, vec![name]),
+                        filepath,
+                        pos,
+                        ExactMatch,
+                        Namespace::Type,
+                        session,
+                        &PendingImports::empty(),
+                    ).any(|tr| tr.mtype == Trait && get_trait_path(&tr, session) == *trait_path);
+                    in_scope.push((trait_path.clone(), res));
+                    res
                 }
-                let methods =
-                    search_for_field_or_method(m, fieldsearchstr, SearchType::StartsWith, session);
-                out.extend(methods);
+            }
+            None => continue,
+        };
+        if is_in_scope {
+            m.trait_path = None;
+        }
+    }
+}
+
+/// Returns the root of the crate of the module at the point, and the path of the module
+/// as segments like `["crate", "shapes", "inner"]`, including inline modules
+fn get_module_segments(
+    filepath: &Path,
+    point: Point,
+    session: &Session,
+) -> Option<(PathBuf, Vec<String>)> {
+    let (crateroot, mut segments) = get_module_path(filepath, session)?;
+    let msrc = session.load_file_and_mask_comments(filepath);
+    segments.extend(scopes::get_local_module_path(msrc.as_src(), point));
+    Some((crateroot, segments))
+}
+
+/// Checks if the point is in the body of a trait or of a trait impl,
+/// where items take the visibility of the trait
+fn is_in_trait_or_trait_impl(src: Src, point: Point) -> bool {
+    let scope_start = scopes::scope_start(src, point);
+    if scope_start == 0 {
+        return false;
+    }
+    let outer_start = scopes::scope_start(src, scope_start - 1);
+    for (start, end) in src.from(outer_start).iter_stmts() {
+        let (start, end) = (outer_start + start, outer_start + end);
+        if !(start < point && point < end) {
+            continue;
+        }
+        let blob = util::trim_visibility(&src[start..end]).trim_left();
+        let blob = if blob.starts_with("unsafe ") {
+            blob[7..].trim_left()
+        } else {
+            blob
+        };
+        if blob.starts_with("trait ") {
+            return true;
+        }
+        if blob.starts_with("impl") {
+            if let Some(n) = blob.find('{') {
+                let decl = format!("{}}}", &blob[..n + 1]);
+                return ast::parse_impl(decl).trait_path.is_some();
+            }
+        }
+        return false;
+    }
+    false
+}
+
+/// Returns the visibility the item of the match is declared with
+fn get_visibility(m: &Match, session: &Session) -> Visibility {
+    let msrc = session.load_file_and_mask_comments(&m.filepath);
+    let src = msrc.as_src();
+    if m.mtype == StructField {
+        // the point of a field is at the start of its declaration
+        return util::parse_visibility(&src[m.point..]);
+    }
+    if m.mtype == Function && is_in_trait_or_trait_impl(src, m.point) {
+        return Visibility::Public;
+    }
+    // the declaration starts after the previous item or attribute
+    let decl_start = src[..m.point]
+        .rfind(|c| c == ';' || c == '{' || c == '}' || c == ']')
+        .map_or(0, |n| n + 1);
+    util::parse_visibility(&src[decl_start..m.point])
+}
+
+/// Checks if the item of the match can be accessed from the point, following its visibility.
+/// Items of files whose crate can't be found are assumed accessible,
+/// unless they are in the same file as the point.
+pub fn is_accessible(m: &Match, filepath: &Path, pos: Point, session: &Session) -> bool {
+    match m.mtype {
+        StructField | Function | Struct | Enum | Trait => {}
+        core::MatchType::Type | core::MatchType::Const | core::MatchType::Static => {}
+        _ => return true,
+    }
+    let visibility = get_visibility(m, session);
+    if visibility == Visibility::Public {
+        return true;
+    }
+    let item_module = get_module_segments(&m.filepath, m.point, session);
+    let cursor_module = get_module_segments(filepath, pos, session);
+    let ((item_crate, item_module), (cursor_crate, cursor_module)) = match (
+        item_module,
+        cursor_module,
+    ) {
+        (Some(item_module), Some(cursor_module)) => (item_module, cursor_module),
+        _ if m.filepath.as_path() == filepath => {
+            let local_module = |point| {
+                let msrc = session.load_file_and_mask_comments(filepath);
+                let mut segments = vec!["crate".to_owned()];
+                segments.extend(scopes::get_local_module_path(msrc.as_src(), point));
+                (filepath.to_path_buf(), segments)
             };
+            (local_module(m.point), local_module(pos))
+        }
+        _ => return true,
+    };
+    // items which aren't public are only accessible in their own crate
+    if item_crate != cursor_crate {
+        return false;
+    }
+    let scope = match visibility {
+        Visibility::Public => return true,
+        Visibility::Crate => return true,
+        Visibility::Private => item_module,
+        Visibility::Super => {
+            let len = item_module.len().saturating_sub(1).max(1);
+            item_module[..len].to_vec()
         }
-        // If Deref to an ordinary type
-        else {
-            let deref_type_path = core::Path {
-                global: false,
-                segments: vec![core::PathSegment {
-                    name: impl_match.generic_args.first().unwrap().clone(),
-                    types: Vec::new(),
-                }],
+        Visibility::In(path) => {
+            let mut scope = match path.first().map(|s| s.as_str()) {
+                Some("self") | Some("super") => item_module.clone(),
+                _ => vec![item_module[0].clone()],
             };
-            let type_match = resolve_path_with_str(
-                &deref_type_path,
-                fpath,
-                0,
-                SearchType::ExactMatch,
+            for segment in path {
+                match segment.as_str() {
+                    "crate" | "self" => {}
+                    "super" => {
+                        if scope.len() > 1 {
+                            scope.pop();
+                        }
+                    }
+                    _ => scope.push(segment),
+                }
+            }
+            scope
+        }
+    };
+    cursor_module.starts_with(&scope)
+}
+
+/// Files in the rust source tree which have well-known blanket impls, like `ToString`
+const BLANKET_IMPL_FILES: [(&str, &str); 1] = [("alloc", "string.rs")];
+
+/// Returns blanket impls like `impl<T: Display> ToString for T` at the top level of the file.
+/// Impls without trait bounds, like `impl<T> From<T> for T`, are skipped since they
+/// would add the same methods to every type.
+fn search_for_blanket_impls(filepath: &Path, session: &Session) -> Rc<Vec<core::BlanketImpl>> {
+    if let Some(impls) = session.get_blanket_impls(filepath) {
+        return impls;
+    }
+    let src = session.load_file_and_mask_comments(filepath);
+    let mut impls = Vec::new();
+    for (start, end) in src.as_src().iter_stmts() {
+        let blob = &src[start..end];
+        if !blob.starts_with("impl") {
+            continue;
+        }
+        let n = match blob.find('{') {
+            Some(n) => n,
+            None => continue,
+        };
+        let decl = &blob[..n + 1];
+        if decl.contains('!') {
+            continue;
+        }
+        let decl = format!("{}}}", decl);
+        let implres = ast::parse_impl(decl.clone());
+        let (name_path, trait_path) = match (implres.name_path, implres.trait_path) {
+            (Some(name_path), Some(trait_path)) => (name_path, trait_path),
+            _ => continue,
+        };
+        if name_path.segments.len() != 1 {
+            continue;
+        }
+        let generics = ast::parse_generics(decl, filepath);
+        let self_param = generics
+            .inner
+            .iter()
+            .find(|arg| arg.name() == name_path.segments[0].name);
+        if let Some(self_param) = self_param {
+            let bounds: Vec<String> = self_param
+                .bounds
+                .trait_names()
+                .into_iter()
+                .filter(|name| *name != "Sized")
+                .map(|name| name.to_owned())
+                .collect();
+            if !bounds.is_empty() {
+                debug!("blanket impl {:?} for {:?}", trait_path, bounds);
+                impls.push(core::BlanketImpl {
+                    trait_path: trait_path,
+                    bounds: bounds,
+                    point: start,
+                });
+            }
+        }
+    }
+    session.cache_blanket_impls(filepath, impls)
+}
+
+/// Returns the files blanket impls are searched in, which are the modules of the crate
+/// the cursor is in, the roots of its dependencies, and the well-known ones of std
+fn blanket_impl_files(session: &Session) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Some(filepath) = session.crate_context() {
+        let crateroot = get_crate_root(&filepath, session);
+        files.extend(fileres::get_crate_files(&crateroot, session));
+        if !files.contains(&filepath) {
+            files.push(filepath.clone());
+        }
+        files.extend(fileres::get_dependency_roots(&filepath, session));
+    }
+    if let Some(srcpath) = session.rust_src_path() {
+        for &(krate, file) in BLANKET_IMPL_FILES.iter() {
+            files.push(session.rust_src_file(&srcpath, krate, file));
+        }
+    }
+    let mut seen = HashSet::new();
+    files.retain(|path| {
+        seen.insert(path.clone()) && (path.exists() || session.contains_file(path))
+    });
+    files
+}
+
+/// Searches methods provided by blanket impls, for a type which implements `impl_traits`
+fn search_for_blanket_impl_methods(
+    impl_traits: &[String],
+    searchstr: &str,
+    search_type: SearchType,
+    session: &Session,
+) -> Vec<Match> {
+    let mut out = Vec::new();
+    if impl_traits.is_empty() {
+        return out;
+    }
+    for filepath in blanket_impl_files(session) {
+        for imp in search_for_blanket_impls(&filepath, session).iter() {
+            let trait_name = match imp.trait_path.segments.last() {
+                Some(seg) => &seg.name,
+                None => continue,
+            };
+            if impl_traits.contains(trait_name)
+                || !imp.bounds.iter().all(|bound| impl_traits.contains(bound))
+            {
+                continue;
+            }
+            debug!("found blanket impl of {} in {:?}", trait_name, filepath);
+            let src = session.load_file(&filepath);
+            let mut methods = Vec::new();
+            if let Some(n) = src[imp.point..].find('{') {
+                methods.extend(search_scope_for_methods(
+                    imp.point + n + 1,
+                    src.as_src(),
+                    searchstr,
+                    &filepath,
+                    search_type,
+                ));
+            }
+            // default methods of the trait
+            let trait_match = resolve_path(
+                &imp.trait_path,
+                &filepath,
+                imp.point,
+                ExactMatch,
                 Namespace::Type,
                 session,
+                &PendingImports::empty(),
             ).nth(0);
-            if let Some(m) = type_match {
-                let methods =
-                    search_for_field_or_method(m, fieldsearchstr, SearchType::StartsWith, session);
-                out.extend(methods);
+            if let Some(tr) = trait_match {
+                let trait_path = get_trait_path(&tr, session);
+                for method in &mut methods {
+                    method.trait_path = Some(trait_path.clone());
+                }
+                let trait_src = session.load_file(&tr.filepath);
+                if let Some(n) = trait_src[tr.point..].find('{') {
+                    for m in search_scope_for_methods(
+                        tr.point + n + 1,
+                        trait_src.as_src(),
+                        searchstr,
+                        &tr.filepath,
+                        search_type,
+                    ) {
+                        if !methods.iter().any(|method| method.matchstr == m.matchstr) {
+                            methods.push(Match {
+                                trait_path: Some(trait_path.clone()),
+                                ..m
+                            });
+                        }
+                    }
+                }
             }
+            out.extend(methods);
+        }
+    }
+    out
+}
+
+/// Maximum number of auto-deref steps, which guards against `Deref` cycles
+const MAX_DEREF_STEPS: usize = 8;
+
+/// Returns the types `m` auto-derefs to, starting from `m` itself,
+/// like `Rc<String>` -> `String` -> `str`
+pub fn deref_chain(m: Match, session: &Session) -> Vec<Match> {
+    let mut chain: Vec<Match> = Vec::new();
+    let mut next = Some(m);
+    while let Some(m) = next.take() {
+        let is_cycle = chain.iter().any(|prev| {
+            prev.matchstr == m.matchstr && prev.filepath == m.filepath && prev.point == m.point
+                && prev.generic_types == m.generic_types
+        });
+        if is_cycle || chain.len() > MAX_DEREF_STEPS {
+            debug!("stop auto-deref at {:?}", m);
+            break;
         }
+        next = match m.mtype {
+            Struct | Enum => get_deref_target(&m, session),
+            _ => None,
+        };
+        chain.push(m);
     }
+    chain
+}
 
-    out.into_iter()
+/// Returns the `Target` of the `Deref` impl for the type
+fn get_deref_target(type_match: &Match, session: &Session) -> Option<Match> {
+    let impl_match = search_for_impls(
+        type_match.point,
+        &type_match.matchstr,
+        &type_match.filepath,
+        type_match.local,
+        true,
+        session,
+        &PendingImports::empty(),
+    ).find(|m| m.matchstr == "Deref")?;
+    let type_arg = impl_match.generic_args.first()?;
+    debug!(
+        "Found a Deref Implementation for {} to {}",
+        type_match.matchstr, type_arg
+    );
+
+    // If Deref to a slice, like Vec<T>
+    if type_arg.starts_with('[') && type_arg.ends_with(']') {
+        let elem = type_arg[1..type_arg.len() - 1].trim();
+        generic_arg_to_path(elem, type_match).and_then(|elem_path| get_slice_match(elem_path, session))
+    }
+    // If Deref to a generic type, resolved where it was written,
+    // like the trait of `Box<dyn Trait>` declared in a function
+    else if let Some(inner_type_path) = generic_arg_to_path(type_arg, type_match) {
+        let type_match = resolve_path_with_str(
+            &inner_type_path.path,
+            &inner_type_path.filepath,
+            inner_type_path.point,
+            SearchType::ExactMatch,
+            Namespace::Type,
+            session,
+        ).nth(0);
+        let subpath = get_subpathsearch(&inner_type_path);
+        type_match.map(|mut m| {
+            if let Some(path) = subpath {
+                m.generic_types.push(path);
+            }
+            m
+        })
+    }
+    // If Deref to an ordinary type
+    else {
+        let deref_type_path = core::Path::from_vec(false, vec![type_arg]);
+        resolve_path_with_str(
+            &deref_type_path,
+            &type_match.filepath,
+            0,
+            SearchType::ExactMatch,
+            Namespace::Type,
+            session,
+        ).nth(0)
+    }
 }
 
 fn generic_arg_to_path(type_str: &str, m: &Match) -> Option<core::PathSearch> {
@@ -159,27 +159,84 @@ fn get_local_module_path_(msrc: Src, point: Point, out: &mut Vec<String>) {
     }
 }
 
-pub fn get_module_file_from_path(msrc: Src, point: Point, parentdir: &Path) -> Option<PathBuf> {
-    let mut iter = msrc.iter_stmts();
-    while let Some((start, end)) = iter.next() {
+/// Checks if the point is in an item which is only compiled for tests,
+/// like a `#[cfg(test)]` module or a `#[test]` function
+pub fn is_in_test_scope(msrc: Src, point: Point) -> bool {
+    let mut has_test_attr = false;
+    for (start, end) in msrc.iter_stmts() {
         let blob = msrc.from_to(start, end);
-        if blob.starts_with("#[path ") {
-            if let Some((_, modend)) = iter.next() {
-                if start < point && modend > point {
-                    let pathstart = blob.find('"').unwrap() + 1;
-                    let pathend = blob[pathstart..].find('"').unwrap();
-                    let path = &blob[pathstart..pathstart + pathend];
-
-                    debug!("found a path attribute, path = |{}|", path);
-                    let filepath = parentdir.join(path);
-                    if filepath.exists() {
-                        return Some(filepath);
-                    }
-                }
+        if blob.starts_with("#[") {
+            has_test_attr |= blob.starts_with("#[cfg(test)") || &blob[..] == "#[test]";
+            continue;
+        }
+        if start < point && end > point {
+            if has_test_attr {
+                return true;
             }
+            return match blob.find('{') {
+                Some(n) if start + n < point => {
+                    is_in_test_scope(blob.from(n + 1), point - start - n - 1)
+                }
+                _ => false,
+            };
         }
+        has_test_attr = false;
     }
-    None
+    false
+}
+
+/// Returns the ranges of the outer attributes right before the item at `itemstart`,
+/// nearest first
+pub fn get_preceding_attributes(msrc: &str, itemstart: Point) -> Vec<SourceByteRange> {
+    let mut out = Vec::new();
+    let mut pos = itemstart;
+    loop {
+        let end = msrc[..pos].trim_right().len();
+        if !msrc[..end].ends_with(']') {
+            break;
+        }
+        // the point right after `#[`
+        let inner_start = match find_close(msrc[..end - 1].as_bytes().iter().rev(), b']', b'[', 0) {
+            Some(count) => end - 1 - count,
+            None => break,
+        };
+        if !msrc[..inner_start].ends_with("#[") {
+            break;
+        }
+        pos = inner_start - 2;
+        out.push((pos, end));
+    }
+    out
+}
+
+/// Parses the value of an attribute like `#[path = "platform/linux.rs"]`
+pub fn parse_path_attribute(attr: &str) -> Option<&str> {
+    let inner = attr.trim().trim_left_matches("#[").trim_right_matches(']').trim();
+    if !inner.starts_with("path") {
+        return None;
+    }
+    let value = inner["path".len()..].trim_left();
+    if !value.starts_with('=') {
+        return None;
+    }
+    let value = value[1..].trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        Some(&value[1..value.len() - 1])
+    } else {
+        None
+    }
+}
+
+#[test]
+fn finds_path_attribute_of_mod() {
+    let src = "#[cfg(unix)]\n#[path = \"sys/unix.rs\"]\nmod imp;";
+    let itemstart = src.find("mod").unwrap();
+    let attrs = get_preceding_attributes(src, itemstart);
+    assert_eq!(attrs.len(), 2);
+    let (start, end) = attrs[0];
+    assert_eq!(parse_path_attribute(&src[start..end]), Some("sys/unix.rs"));
+    let (start, end) = attrs[1];
+    assert_eq!(parse_path_attribute(&src[start..end]), None);
 }
 
 pub fn find_impl_start(msrc: Src, point: Point, scopestart: Point) -> Option<Point> {
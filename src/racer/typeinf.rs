@@ -130,6 +130,7 @@ pub fn get_type_of_self(
                     generic_args: Vec::new(),
                     generic_types: Vec::new(),
                     docs: String::new(),
+                    trait_path: None,
                 }))
             })
         }
@@ -440,15 +441,12 @@ pub fn get_function_declaration(fnmatch: &Match, session: &Session) -> String {
     src[start..end + start].to_owned()
 }
 
-pub fn get_return_type_of_function(
-    fnmatch: &Match,
-    contextm: &Match,
-    session: &Session,
-) -> Option<core::Ty> {
+// Returns the declaration of the function without its body, wrapped in
+// "impl blah { }" so that methods get parsed correctly too
+fn get_function_decl_for_parsing(fnmatch: &Match, session: &Session) -> Option<String> {
     let src = session.load_file(&fnmatch.filepath);
     let point = scopes::expect_stmt_start(src.as_src(), fnmatch.point);
-    let out = src[point..].find(|c| c == '{' || c == ';').and_then(|n| {
-        // wrap in "impl blah { }" so that methods get parsed correctly too
+    src[point..].find(|c| c == '{' || c == ';').map(|n| {
         let mut decl = String::new();
         decl.push_str("impl blah {");
         decl.push_str(&src[point..(point + n + 1)]);
@@ -458,6 +456,24 @@ pub fn get_return_type_of_function(
         } else {
             decl.push_str("}}");
         }
+        decl
+    })
+}
+
+/// Get generics, argument types and the return type of the function
+pub fn get_function_signature(fnmatch: &Match, session: &Session) -> Option<ast::FnSignature> {
+    let decl = get_function_decl_for_parsing(fnmatch, session)?;
+    debug!("get_function_signature: passing in |{}|", decl);
+    ast::parse_fn_signature(decl, Scope::from_match(fnmatch))
+}
+
+pub fn get_return_type_of_function(
+    fnmatch: &Match,
+    contextm: &Match,
+    session: &Session,
+) -> Option<core::Ty> {
+    let src = session.load_file(&fnmatch.filepath);
+    let out = get_function_decl_for_parsing(fnmatch, session).and_then(|decl| {
         debug!("get_return_type_of_function: passing in |{}|", decl);
         ast::parse_fn_output(decl, Scope::from_match(fnmatch))
     });
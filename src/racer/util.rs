@@ -7,6 +7,7 @@ use std::rc::Rc;
 
 use core::SearchType::{self, ExactMatch, StartsWith};
 use core::{IndexedSource, Location, LocationExt, Point, Session, SessionExt};
+use manifest;
 
 #[cfg(unix)]
 pub const PATH_SEP: char = ':';
@@ -308,10 +309,11 @@ impl ::std::fmt::Display for RustSrcPathError {
             ),
             RustSrcPathError::NotRustSourceTree(ref path) => write!(
                 f,
-                "Unable to find libstd under RUST_SRC_PATH. N.B. \
+                "Unable to find the standard library under RUST_SRC_PATH. N.B. \
                  RUST_SRC_PATH variable needs to point to the *src* \
                  directory inside a rust checkout e.g. \
-                 \"/home/foouser/src/rust/src\". \
+                 \"/home/foouser/src/rust/src\", or to the *library* \
+                 directory in newer ones. \
                  Current value \"{:?}\"",
                 path
             ),
@@ -326,21 +328,138 @@ fn check_rust_sysroot() -> Option<path::PathBuf> {
 
     if let Ok(output) = cmd.output() {
         if let Ok(s) = String::from_utf8(output.stdout) {
-            let sysroot = path::Path::new(s.trim());
-            let srcpath = sysroot.join("lib/rustlib/src/rust/src");
-            if srcpath.exists() {
-                return Some(srcpath);
+            return rust_src_in_sysroot(path::Path::new(s.trim()));
+        }
+    }
+    None
+}
+
+/// Returns the rust source tree of the `rust-src` component installed in the sysroot
+fn rust_src_in_sysroot(sysroot: &path::Path) -> Option<path::PathBuf> {
+    // newer rust-src components have the standard library in `library`
+    for dir in &["lib/rustlib/src/rust/library", "lib/rustlib/src/rust/src"] {
+        let srcpath = sysroot.join(dir);
+        if is_rust_src_tree(&srcpath) {
+            return Some(srcpath);
+        }
+    }
+    None
+}
+
+/// Returns the nearest `rust-toolchain.toml` or `rust-toolchain` file above the path
+pub fn find_toolchain_file(from_path: &path::Path) -> Option<path::PathBuf> {
+    let mut dir = if from_path.is_dir() {
+        Some(from_path)
+    } else {
+        from_path.parent()
+    };
+    while let Some(d) = dir {
+        for name in &["rust-toolchain.toml", "rust-toolchain"] {
+            let toolchain_file = d.join(name);
+            if toolchain_file.is_file() {
+                return Some(toolchain_file);
             }
         }
+        dir = d.parent();
     }
     None
 }
 
+/// Returns the toolchain of a toolchain file, which is either just its name like
+/// `nightly-2018-05-01`, or a `[toolchain]` table with a `channel` key
+pub fn parse_toolchain_file(src: &str) -> Option<String> {
+    let src = src.trim();
+    if src.contains('[') || src.contains('=') {
+        let tables = manifest::parse_tables(src);
+        manifest::find_table(&tables, "toolchain")?
+            .get("channel")
+            .and_then(manifest::parse_string)
+    } else if src.is_empty() {
+        None
+    } else {
+        src.lines().next().map(|line| line.trim().to_owned())
+    }
+}
+
+/// Returns the path of the custom toolchain a toolchain file selects with a `path` key in
+/// its `[toolchain]` table, relative to the directory of the file
+pub fn parse_toolchain_path(src: &str) -> Option<String> {
+    let tables = manifest::parse_tables(src);
+    manifest::find_table(&tables, "toolchain")?
+        .get("path")
+        .and_then(manifest::parse_string)
+}
+
+/// Returns the rust source tree of the toolchain selected by the toolchain file,
+/// if it's installed and has `rust-src`
+pub fn toolchain_file_rust_src(toolchain_file: &path::Path) -> Option<path::PathBuf> {
+    use std::fs;
+
+    let src = match fs::read_to_string(toolchain_file) {
+        Ok(src) => src,
+        Err(err) => {
+            warn!("[toolchain_file_rust_src] failed to read {:?}: {}", toolchain_file, err);
+            return None;
+        }
+    };
+    if let Some(toolchain_path) = parse_toolchain_path(&src) {
+        return rust_src_in_sysroot(&toolchain_file.parent()?.join(toolchain_path));
+    }
+    find_toolchain_rust_src(&parse_toolchain_file(&src)?)
+}
+
+/// Returns the rust source tree of the toolchain selected by a `rust-toolchain` or
+/// `rust-toolchain.toml` file of the project the path is in, if it's installed
+/// and has `rust-src`
+pub fn get_project_rust_src_path(from_path: &path::Path) -> Option<path::PathBuf> {
+    toolchain_file_rust_src(&find_toolchain_file(from_path)?)
+}
+
+/// Returns the rust source tree of the toolchain installed by rustup, if it has `rust-src`.
+/// Toolchain directories are named like `nightly-x86_64-unknown-linux-gnu`.
+pub fn find_toolchain_rust_src(channel: &str) -> Option<path::PathBuf> {
+    use std::env;
+
+    let rustup_home = env::var_os("RUSTUP_HOME")
+        .map(path::PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME")
+                .or_else(|| env::var_os("USERPROFILE"))
+                .map(|home| path::PathBuf::from(home).join(".rustup"))
+        })?;
+    find_rust_src_in_toolchains(&rustup_home.join("toolchains"), channel)
+}
+
+fn find_rust_src_in_toolchains(toolchains: &path::Path, channel: &str) -> Option<path::PathBuf> {
+    use std::fs;
+
+    let prefix = format!("{}-", channel);
+    let mut candidates: Vec<_> = fs::read_dir(toolchains)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| {
+                    name == channel
+                        // `nightly` shouldn't select `nightly-2018-05-01-x86_64-..`
+                        || (name.starts_with(&prefix)
+                            && !name[prefix.len()..].starts_with(|c: char| c.is_digit(10)))
+                })
+        })
+        .collect();
+    candidates.sort();
+    candidates
+        .iter()
+        .filter_map(|sysroot| rust_src_in_sysroot(sysroot))
+        .next()
+}
+
 /// Get the path for Rust standard library source code.
-/// Checks first the paths in the `RUST_SRC_PATH` environment variable.
+/// Checks first the paths in the `RUST_SRC_PATH` environment variable, in order.
 ///
 /// If the environment variable is _not_ set, it checks the rust sys
-/// root for the `rust-src` component.
+/// root for the `rust-src` component, in either its `library` or older `src` layout.
 ///
 /// If that isn't available, checks `/usr/local/src/rust/src` and
 /// `/usr/src/rust/src` as default values.
@@ -378,13 +497,24 @@ pub fn get_rust_src_path() -> ::std::result::Result<path::PathBuf, RustSrcPathEr
 
     if let Ok(ref srcpaths) = env::var("RUST_SRC_PATH") {
         if !srcpaths.is_empty() {
-            for path in srcpaths.split(PATH_SEP) {
-                return validate_rust_src_path(path::PathBuf::from(path));
+            // the first valid path wins, or the error of the first one is reported
+            let mut first_err = None;
+            for path in srcpaths.split(PATH_SEP).filter(|path| !path.is_empty()) {
+                match validate_rust_src_path(path::PathBuf::from(path)) {
+                    Ok(path) => return Ok(path),
+                    Err(err) => {
+                        debug!("{:?} is not a valid rust source path: {}", path, err);
+                        first_err = first_err.or(Some(err));
+                    }
+                }
+            }
+            if let Some(err) = first_err {
+                return Err(err);
             }
         }
     };
 
-    debug!("Nope. Trying rustc --sysroot and appending lib/rustlib/src/rust/src to that.");
+    debug!("Nope. Trying rustc --sysroot and appending lib/rustlib/src/rust/library or src.");
 
     if let Some(path) = check_rust_sysroot() {
         return validate_rust_src_path(path);
@@ -410,13 +540,143 @@ fn validate_rust_src_path(
 ) -> ::std::result::Result<path::PathBuf, RustSrcPathError> {
     if !path.exists() {
         Err(RustSrcPathError::DoesNotExist(path.to_path_buf()))
-    } else if !path.join("libstd").exists() {
-        Err(RustSrcPathError::NotRustSourceTree(path.join("libstd")))
+    } else if !is_rust_src_tree(&path) {
+        // the directory the standard library is missing from, in the layout the path is in
+        let libstd = if path.ends_with("library") || path.join("std").exists() {
+            path.join("std").join("src")
+        } else {
+            path.join("libstd")
+        };
+        Err(RustSrcPathError::NotRustSourceTree(libstd))
     } else {
         Ok(path.to_path_buf())
     }
 }
 
+/// Checks if the directory has the standard library in either layout of the rust source tree
+fn is_rust_src_tree(path: &path::Path) -> bool {
+    path.join("libstd").exists() || is_library_layout(path)
+}
+
+/// Checks if the rust source tree has the `library/std/src/lib.rs` layout,
+/// rather than the older `src/libstd/lib.rs` one
+pub fn is_library_layout(srcpath: &path::Path) -> bool {
+    !srcpath.join("libstd").exists() && srcpath.join("std").join("src").join("lib.rs").exists()
+}
+
+/// Returns the path of a file of the standard library crate in the rust source tree,
+/// like `libcore/num/mod.rs` or `core/src/num/mod.rs` for `("core", "num/mod.rs")`,
+/// depending on whether the tree has the layout `is_library_layout` checks
+pub fn rust_src_file(
+    srcpath: &path::Path,
+    library_layout: bool,
+    krate: &str,
+    relpath: &str,
+) -> path::PathBuf {
+    if library_layout {
+        srcpath.join(krate).join("src").join(relpath)
+    } else {
+        srcpath.join(format!("lib{}", krate)).join(relpath)
+    }
+}
+
+#[test]
+fn test_get_rust_src_path_skips_invalid_entries() {
+    use std::env;
+
+    let _guard = TEST_SEMAPHORE.lock().unwrap();
+
+    let original = env::var_os("RUST_SRC_PATH");
+    let valid = match original {
+        Some(ref path) => path.to_str().unwrap().split(PATH_SEP).next().unwrap().to_owned(),
+        None => check_rust_sysroot().unwrap().to_str().unwrap().to_owned(),
+    };
+    env::set_var("RUST_SRC_PATH", format!("test_path{}{}", PATH_SEP, valid));
+    let result = get_rust_src_path();
+
+    match original {
+        Some(path) => env::set_var("RUST_SRC_PATH", path),
+        None => env::remove_var("RUST_SRC_PATH"),
+    }
+    assert_eq!(Ok(path::PathBuf::from(valid)), result);
+}
+
+#[test]
+fn test_parse_toolchain_file() {
+    assert_eq!(
+        parse_toolchain_file("nightly-2018-05-01\n"),
+        Some("nightly-2018-05-01".to_owned())
+    );
+    let toml = "[toolchain]\nchannel = \"1.26.0\"\ncomponents = [\"rust-src\"]\n";
+    assert_eq!(parse_toolchain_file(toml), Some("1.26.0".to_owned()));
+    assert_eq!(parse_toolchain_path(toml), None);
+    let toml = "[toolchain]\npath = \"toolchains/custom\"\n";
+    assert_eq!(parse_toolchain_path(toml), Some("toolchains/custom".to_owned()));
+    assert_eq!(parse_toolchain_file("\n"), None);
+}
+
+#[test]
+fn test_find_rust_src_in_toolchains() {
+    use std::fs;
+    use tempfile;
+
+    let toolchains = tempfile::tempdir().unwrap();
+    let names = [
+        "nightly-2018-05-01-x86_64-unknown-linux-gnu",
+        "nightly-x86_64-unknown-linux-gnu",
+    ];
+    for name in &names {
+        let std_dir = toolchains
+            .path()
+            .join(name)
+            .join("lib/rustlib/src/rust/library/std/src");
+        fs::create_dir_all(&std_dir).unwrap();
+        fs::write(std_dir.join("lib.rs"), "").unwrap();
+    }
+    let nightly = toolchains.path().join("nightly-x86_64-unknown-linux-gnu");
+    assert_eq!(
+        find_rust_src_in_toolchains(toolchains.path(), "nightly"),
+        Some(nightly.join("lib/rustlib/src/rust/library"))
+    );
+    let dated = toolchains.path().join("nightly-2018-05-01-x86_64-unknown-linux-gnu");
+    assert_eq!(
+        find_rust_src_in_toolchains(toolchains.path(), "nightly-2018-05-01"),
+        Some(dated.join("lib/rustlib/src/rust/library"))
+    );
+    assert_eq!(find_rust_src_in_toolchains(toolchains.path(), "stable"), None);
+}
+
+#[test]
+fn test_rust_src_file_in_both_layouts() {
+    use std::fs;
+    use tempfile;
+
+    let old = tempfile::tempdir().unwrap();
+    fs::create_dir_all(old.path().join("libstd")).unwrap();
+    assert_eq!(
+        rust_src_file(old.path(), is_library_layout(old.path()), "core", "num/mod.rs"),
+        old.path().join("libcore").join("num/mod.rs")
+    );
+
+    let new = tempfile::tempdir().unwrap();
+    fs::create_dir_all(new.path().join("std").join("src")).unwrap();
+    fs::write(new.path().join("std").join("src").join("lib.rs"), "").unwrap();
+    assert!(is_rust_src_tree(new.path()));
+    assert_eq!(
+        rust_src_file(new.path(), is_library_layout(new.path()), "core", "num/mod.rs"),
+        new.path().join("core").join("src").join("num/mod.rs")
+    );
+
+    let broken = tempfile::tempdir().unwrap();
+    fs::create_dir_all(broken.path().join("std")).unwrap();
+    assert_eq!(
+        validate_rust_src_path(broken.path().to_path_buf()),
+        Err(RustSrcPathError::NotRustSourceTree(
+            broken.path().join("std").join("src")
+        ))
+    );
+}
+
 #[cfg(test)]
 lazy_static! {
     static ref TEST_SEMAPHORE: ::std::sync::Mutex<()> = ::std::sync::Mutex::new(());
@@ -625,6 +885,67 @@ fn test_trim_visibility() {
     assert_eq!(trim_visibility("pub (in super)  const fn"), "const fn");
 }
 
+/// The visibility an item is declared with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Visibility {
+    /// `pub`
+    Public,
+    /// `pub(crate)`
+    Crate,
+    /// `pub(super)`
+    Super,
+    /// `pub(in path)`, holding the segments of the path
+    In(Vec<String>),
+    /// no visibility, or `pub(self)`
+    Private,
+}
+
+/// Parses the visibility at the start of an item declaration.
+pub fn parse_visibility(blob: &str) -> Visibility {
+    let blob = blob.trim_left();
+    if blob.starts_with("crate ") {
+        return Visibility::Crate;
+    }
+    if !blob.starts_with("pub") || blob[3..].starts_with(is_ident_char) {
+        return Visibility::Private;
+    }
+    let rest = blob[3..].trim_left();
+    if !rest.starts_with('(') {
+        return Visibility::Public;
+    }
+    let restriction = match rest.find(')') {
+        Some(end) => rest[1..end].trim(),
+        None => return Visibility::Public,
+    };
+    match restriction {
+        "crate" => Visibility::Crate,
+        "super" => Visibility::Super,
+        "self" => Visibility::Private,
+        _ if restriction.starts_with("in ") => Visibility::In(
+            restriction[3..]
+                .split("::")
+                .map(|s| s.trim().to_owned())
+                .collect(),
+        ),
+        // e.g. a tuple struct field like `pub (u32, u32)`
+        _ => Visibility::Public,
+    }
+}
+
+#[test]
+fn test_parse_visibility() {
+    assert_eq!(parse_visibility("pub fn"), Visibility::Public);
+    assert_eq!(parse_visibility("  fn"), Visibility::Private);
+    assert_eq!(parse_visibility("pubfn"), Visibility::Private);
+    assert_eq!(parse_visibility("pub(crate) struct"), Visibility::Crate);
+    assert_eq!(parse_visibility("pub (super) x: u32"), Visibility::Super);
+    assert_eq!(parse_visibility("pub(self) const"), Visibility::Private);
+    assert_eq!(
+        parse_visibility("pub(in crate::foo) fn"),
+        Visibility::In(vec!["crate".to_owned(), "foo".to_owned()])
+    );
+}
+
 /// Checks if the completion point is in a function declaration by looking
 /// to see if the second-to-last word is `fn`.
 pub fn in_fn_name(line_before_point: &str) -> bool {
@@ -3987,3 +3987,2064 @@ fn main() {
     let got = get_definition(src, None);
     assert_eq!(got.matchstr, "EnumB");
 }
+
+mod trait_object {
+    use super::*;
+    #[test]
+    fn completes_methods_for_boxed_dyn_trait_field() {
+        let src = "
+        fn main() {
+            trait Handler {
+                fn handle(&self);
+            }
+            struct Server {
+                handler: Box<dyn Handler + Send>,
+            }
+            fn run(s: Server) {
+                s.handler.hand~
+            }
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "handle");
+    }
+
+    #[test]
+    fn completes_methods_for_dyn_trait_ref_let() {
+        let src = "
+        fn main() {
+            trait Handler {
+                fn handle(&self);
+            }
+            fn run(h: &mut Handler) {
+                let r: &mut dyn Handler = h;
+                r.hand~
+            }
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "handle");
+    }
+
+    #[test]
+    fn completes_supertrait_methods_for_rc_dyn_trait() {
+        let src = "
+        use std::rc::Rc;
+        trait Named {
+            fn name(&self) -> String;
+        }
+        trait Handler: Named {
+            fn handle(&self);
+        }
+        fn main() {
+            let h: Rc<dyn Handler> = make();
+            h.na~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "name");
+    }
+
+    #[test]
+    fn completes_methods_of_smart_pointer_to_dyn_trait() {
+        let src = "
+        use std::rc::Rc;
+        trait Handler {
+            fn handle(&self);
+        }
+        fn main() {
+            let h: Rc<dyn Handler> = make();
+            h.clo~
+        }
+        ";
+        let got = get_all_completions(src, None);
+        assert!(got.iter().any(|m| m.matchstr == "clone"));
+    }
+}
+
+mod generic_fn_inference {
+    use super::*;
+    #[test]
+    fn infers_generic_fn_return_type_from_argument() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn identity<T>(x: T) -> T { x }
+        fn main() {
+            let foo = Foo;
+            identity(foo).foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn infers_generic_wrapper_from_reference_argument() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        struct Wrapper<T> {
+            inner: T,
+        }
+        fn wrap<T>(x: &T) -> Wrapper<T> { unimplemented!() }
+        fn main() {
+            let foo = Foo;
+            wrap(&foo).inner.foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn infers_generic_method_return_type_from_turbofish() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        struct Bar;
+        impl Bar {
+            fn make<T>(&self) -> T { unimplemented!() }
+        }
+        fn main() {
+            let bar = Bar;
+            bar.make::<Foo>().foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+}
+
+mod index_expr {
+    use super::*;
+    #[test]
+    fn completes_methods_for_vec_element() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn main() {
+            let v: Vec<Foo> = Vec::new();
+            v[0].foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn completes_methods_for_array_element() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn run(arr: [Foo; 4], i: usize) {
+            arr[i].foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn completes_methods_for_slice_element() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn run(foos: &[Foo]) {
+            foos[1].foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn completes_methods_for_index_impl_output() {
+        let src = "
+        use std::ops::Index;
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        struct Registry<K, V> {
+            items: Vec<(K, V)>,
+        }
+        impl<K, V> Index<K> for Registry<K, V> {
+            type Output = V;
+            fn index(&self, key: K) -> &V { unimplemented!() }
+        }
+        fn run(reg: Registry<u32, Foo>) {
+            reg[0].foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn completes_methods_for_index_output_of_impl_itself() {
+        let src = "
+        use std::ops::{Add, Index};
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        struct Registry<K, V> {
+            items: Vec<(K, V)>,
+        }
+        impl<K, V> Index<K> for Registry<K, V> {
+            fn index(&self, key: K) -> &V {
+                struct Offset;
+                impl Add for Offset {
+                    type Output = u8;
+                    fn add(self, other: Offset) -> u8 { 0 }
+                }
+                unimplemented!()
+            }
+            type Output = V;
+        }
+        fn run(reg: Registry<u32, Foo>) {
+            reg[0].foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn completes_methods_for_hash_map_index_output() {
+        let src = "
+        use std::collections::HashMap;
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn run(map: HashMap<&str, Foo>) {
+            map[\"k\"].foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn completes_slice_methods_for_vec_range() {
+        let src = "
+        fn run(v: Vec<u8>) {
+            v[1..].windo~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "windows");
+    }
+
+    #[test]
+    fn completes_str_methods_for_string_range() {
+        let src = "
+        fn run(s: String) {
+            s[..2].to_upperc~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "to_uppercase");
+    }
+}
+
+mod operator_expr {
+    use super::*;
+    #[test]
+    fn completes_methods_for_add_output() {
+        let src = "
+        use std::ops::Add;
+        struct Vec3 {
+            x: f32,
+        }
+        impl Vec3 {
+            fn length(&self) -> f32 { self.x }
+        }
+        impl Add for Vec3 {
+            type Output = Vec3;
+            fn add(self, other: Vec3) -> Vec3 { self }
+        }
+        fn run(a: Vec3, b: Vec3) {
+            (a + b).len~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "length");
+    }
+
+    const MATRIX_SRC: &str = "
+        use std::ops::Mul;
+        struct Vec3 {
+            x: f32,
+        }
+        impl Vec3 {
+            fn length(&self) -> f32 { self.x }
+        }
+        struct Matrix;
+        impl Matrix {
+            fn transpose(&self) -> Matrix { Matrix }
+        }
+        impl Mul<Vec3> for Matrix {
+            type Output = Vec3;
+            fn mul(self, v: Vec3) -> Vec3 { v }
+        }
+        impl Mul for Matrix {
+            type Output = Matrix;
+            fn mul(self, m: Matrix) -> Matrix { m }
+        }
+        ";
+
+    #[test]
+    fn completes_methods_for_output_of_impl_for_rhs_type() {
+        let src = format!(
+            "{}
+        fn run(m: Matrix, v: Vec3) {{
+            (m * v).len~
+        }}
+        ",
+            MATRIX_SRC
+        );
+        assert_eq!(get_only_completion(&src, None).matchstr, "length");
+    }
+
+    #[test]
+    fn completes_methods_for_output_of_impl_for_self_rhs() {
+        let src = format!(
+            "{}
+        fn run(m: Matrix, n: Matrix) {{
+            (m * n).transp~
+        }}
+        ",
+            MATRIX_SRC
+        );
+        assert_eq!(get_only_completion(&src, None).matchstr, "transpose");
+    }
+
+    #[test]
+    fn completes_methods_for_neg_output_self() {
+        let src = "
+        use std::ops::Neg;
+        struct Vec3 {
+            x: f32,
+        }
+        impl Vec3 {
+            fn length(&self) -> f32 { self.x }
+        }
+        impl Neg for Vec3 {
+            type Output = Self;
+            fn neg(self) -> Self { self }
+        }
+        fn run(v: Vec3) {
+            (-v).len~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "length");
+    }
+
+    #[test]
+    fn completes_methods_for_bitor_output_of_other_type() {
+        let src = "
+        use std::ops::BitOr;
+        struct Flag;
+        struct Flags;
+        impl Flags {
+            fn contains(&self, f: Flag) -> bool { true }
+        }
+        impl BitOr for Flag {
+            type Output = Flags;
+            fn bitor(self, other: Flag) -> Flags { Flags }
+        }
+        fn run(a: Flag, b: Flag) {
+            let flags = a | b;
+            flags.cont~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "contains");
+    }
+}
+
+mod slice_methods {
+    use super::*;
+    #[test]
+    fn completes_slice_methods_for_array() {
+        let src = "
+        fn main() {
+            let a: [u8; 4] = [0; 4];
+            a.so~
+        }
+        ";
+        assert!(
+            get_all_completions(src, None)
+                .iter()
+                .any(|ma| ma.matchstr == "sort")
+        );
+    }
+
+    #[test]
+    fn completes_slice_methods_for_slice_ref() {
+        let src = "
+        struct Foo;
+        fn run(s: &[Foo]) {
+            s.ite~
+        }
+        ";
+        assert!(
+            get_all_completions(src, None)
+                .iter()
+                .any(|ma| ma.matchstr == "iter")
+        );
+    }
+
+    #[test]
+    fn completes_slice_methods_for_vec() {
+        let src = "
+        struct Foo;
+        fn run(v: Vec<Foo>) {
+            v.first_~
+        }
+        ";
+        assert!(
+            get_all_completions(src, None)
+                .iter()
+                .any(|ma| ma.matchstr == "first_mut")
+        );
+    }
+
+    #[test]
+    fn carries_element_type_into_slice_method() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn run(s: &[Foo]) {
+            s.first().unwrap().foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+}
+
+mod tuple_fields {
+    use super::*;
+    #[test]
+    fn completes_positional_fields_for_tuple() {
+        let src = "
+        struct Foo;
+        struct Bar;
+        fn main() {
+            let p = (Foo, Bar);
+            p.~
+        }
+        ";
+        let got = get_all_completions(src, None);
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].matchstr, "0");
+        assert_eq!(got[0].contextstr, "Foo");
+        assert_eq!(got[1].matchstr, "1");
+        assert_eq!(got[1].contextstr, "Bar");
+    }
+
+    #[test]
+    fn completes_methods_for_tuple_field() {
+        let src = "
+        struct Foo;
+        struct Bar;
+        impl Bar {
+            fn bar_method(&self) {}
+        }
+        fn main() {
+            let p = (Foo, Bar);
+            p.1.bar_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "bar_method");
+    }
+
+    #[test]
+    fn completes_methods_for_tuple_field_of_fnarg() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn run(pair: &(Foo, u32)) {
+            pair.0.foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn finds_definition_of_tuple_field_at_tuple_type() {
+        let src = "
+        struct Foo;
+        fn main() {
+            let pair: (Foo, u32) = (Foo, 0);
+            pair.0~;
+        }
+        ";
+        let got = get_definition(src, None);
+        assert_eq!(got.matchstr, "0");
+        assert_eq!(got.contextstr, "Foo");
+        let (cursor, _) = get_pos_and_source(src);
+        assert!(got.point < src.find("pair.0").unwrap());
+        assert!(got.point != cursor);
+    }
+
+    #[test]
+    fn completes_positional_fields_for_tuple_struct() {
+        let src = "
+        struct P(i32, String);
+        fn main() {
+            let p = P(0, String::new());
+            p.~
+        }
+        ";
+        let got = get_all_completions(src, None);
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].matchstr, "0");
+        assert_eq!(got[1].matchstr, "1");
+        assert_eq!(got[1].contextstr, "String");
+    }
+
+    #[test]
+    fn finds_definition_of_tuple_struct_field() {
+        let src = "
+        struct P(i32, String);
+        fn main() {
+            let p = P(0, String::new());
+            p.1~;
+        }
+        ";
+        let got = get_definition(src, None);
+        assert_eq!(got.matchstr, "1");
+        assert_eq!(got.point, src.find("String);").unwrap());
+    }
+
+    #[test]
+    fn completes_methods_for_tuple_struct_field() {
+        let src = "
+        struct P(i32, String);
+        fn main() {
+            let p = P(0, String::new());
+            p.1.push_st~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "push_str");
+    }
+}
+
+mod primitive_methods {
+    use super::*;
+    #[test]
+    fn completes_int_methods_from_macro_impls() {
+        let src = "
+        fn run(x: u32) {
+            x.checked_ad~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "checked_add");
+    }
+
+    #[test]
+    fn completes_char_methods() {
+        let src = "
+        fn run(c: char) {
+            c.is_alphab~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "is_alphabetic");
+    }
+
+    #[test]
+    fn completes_float_methods_for_literal() {
+        let src = "
+        fn main() {
+            let x = 2.0f64;
+            x.sqr~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "sqrt");
+    }
+
+    #[test]
+    fn completes_raw_pointer_methods() {
+        let src = "
+        struct Foo;
+        fn run(p: *const Foo) {
+            p.is_nu~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "is_null");
+    }
+
+    /// A directory selecting a toolchain whose rust-src has the `library/` layout,
+    /// with the impls of raw pointers in `core/src/ptr/const_ptr.rs` and `mut_ptr.rs`,
+    /// and the methods of integers in the `int_impl!` macro of `core/src/num/int_macros.rs`
+    fn setup_library_layout() -> TmpDir {
+        let dir = TmpDir::new();
+        dir.write_path("rust-toolchain.toml", "[toolchain]\npath = \"toolchain\"\n");
+        let library = "toolchain/lib/rustlib/src/rust/library";
+        dir.write_path(&format!("{}/std/src/lib.rs", library), "");
+        dir.write_path(&format!("{}/core/src/lib.rs", library), "pub mod num;\npub mod ptr;");
+        dir.write_path(
+            &format!("{}/core/src/num/mod.rs", library),
+            "#[macro_use]
+            mod int_macros;
+            #[lang = \"i32\"]
+            impl i32 {
+                int_impl! { i32, u32 }
+            }",
+        );
+        dir.write_path(
+            &format!("{}/core/src/num/int_macros.rs", library),
+            "macro_rules! int_impl {
+                ($SelfT:ty, $UnsignedT:ty) => {
+                    pub const fn checked_add(self, rhs: Self) -> Option<Self> { None }
+                }
+            }",
+        );
+        dir.write_path(
+            &format!("{}/core/src/ptr/mod.rs", library),
+            "mod const_ptr;\nmod mut_ptr;",
+        );
+        dir.write_path(
+            &format!("{}/core/src/ptr/const_ptr.rs", library),
+            "impl<T: ?Sized> *const T {
+                pub fn is_null(self) -> bool { false }
+            }",
+        );
+        dir.write_path(
+            &format!("{}/core/src/ptr/mut_ptr.rs", library),
+            "impl<T: ?Sized> *mut T {
+                pub fn is_null(self) -> bool { false }
+            }",
+        );
+        dir
+    }
+
+    #[test]
+    fn completes_raw_pointer_methods_in_library_layout() {
+        let src = "
+        fn run(p: *const u8) {
+            p.is_nu~
+        }
+        ";
+        let got = get_only_completion(src, Some(setup_library_layout()));
+        assert_eq!(got.matchstr, "is_null");
+        assert!(got.filepath.ends_with("library/core/src/ptr/const_ptr.rs"));
+        let src = "
+        fn run(p: *mut u8) {
+            p.is_nu~
+        }
+        ";
+        let got = get_only_completion(src, Some(setup_library_layout()));
+        assert!(got.filepath.ends_with("library/core/src/ptr/mut_ptr.rs"));
+    }
+
+    #[test]
+    fn completes_int_methods_from_macro_defined_in_other_module() {
+        let src = "
+        fn run(x: i32) {
+            x.checked_ad~
+        }
+        ";
+        let got = get_only_completion(src, Some(setup_library_layout()));
+        assert_eq!(got.matchstr, "checked_add");
+        assert!(got.filepath.ends_with("library/core/src/num/int_macros.rs"));
+        assert!(!got.local);
+    }
+
+    #[test]
+    fn finds_definition_of_int_method() {
+        let src = "
+        fn run(x: i64) {
+            x.wrapping_n~eg();
+        }
+        ";
+        let got = get_definition(src, None);
+        assert_eq!(got.matchstr, "wrapping_neg");
+    }
+}
+
+mod auto_deref {
+    use super::*;
+    #[test]
+    fn completes_methods_through_nested_smart_pointers() {
+        let src = "
+        use std::rc::Rc;
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn run(x: Rc<Box<Foo>>) {
+            x.foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn completes_str_methods_through_boxed_string() {
+        let src = "
+        fn run(s: Box<String>) {
+            s.to_lowerc~
+        }
+        ";
+        assert!(
+            get_all_completions(src, None)
+                .iter()
+                .any(|ma| ma.matchstr == "to_lowercase")
+        );
+    }
+
+    #[test]
+    fn completes_methods_for_double_reference() {
+        let src = "
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        fn run(x: &&Foo) {
+            x.foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn stops_at_deref_cycle() {
+        let src = "
+        use std::ops::Deref;
+        struct Foo;
+        impl Foo {
+            fn foo_method(&self) {}
+        }
+        impl Deref for Foo {
+            type Target = Foo;
+            fn deref(&self) -> &Foo { self }
+        }
+        fn run(x: Foo) {
+            x.foo_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "foo_method");
+    }
+
+    #[test]
+    fn finds_definition_through_user_deref() {
+        let src = "
+        use std::ops::Deref;
+        struct Inner;
+        impl Inner {
+            fn inner_method(&self) {}
+        }
+        struct Outer(Inner);
+        impl Deref for Outer {
+            type Target = Inner;
+            fn deref(&self) -> &Inner { &self.0 }
+        }
+        fn run(x: Outer) {
+            x.inner_met~hod();
+        }
+        ";
+        let got = get_definition(src, None);
+        assert_eq!(got.matchstr, "inner_method");
+    }
+}
+
+mod blanket_impls {
+    use super::*;
+    use std::fs;
+    #[test]
+    fn completes_methods_from_blanket_impl_in_same_file() {
+        let src = "
+        trait Shape {
+            fn area(&self) -> f64;
+        }
+        trait Describe {
+            fn describe(&self) -> String;
+        }
+        impl<T: Shape> Describe for T {
+            fn describe(&self) -> String { String::new() }
+        }
+        struct Square;
+        impl Shape for Square {
+            fn area(&self) -> f64 { 1.0 }
+        }
+        fn run(s: Square) {
+            s.descr~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "describe");
+    }
+
+    #[test]
+    fn completes_default_methods_of_blanket_impl_trait() {
+        let src = "
+        trait IteratorExt: Iterator {
+            fn counted(self) -> usize where Self: Sized { 0 }
+        }
+        impl<I: Iterator> IteratorExt for I {}
+        struct Counter;
+        impl Iterator for Counter {
+            type Item = u32;
+            fn next(&mut self) -> Option<u32> { None }
+        }
+        fn run(c: Counter) {
+            c.count~
+        }
+        ";
+        assert!(
+            get_all_completions(src, None)
+                .iter()
+                .any(|ma| ma.matchstr == "counted")
+        );
+    }
+
+    #[test]
+    fn completes_to_string_for_display_impl() {
+        let src = "
+        use std::fmt;
+        struct Point;
+        impl fmt::Display for Point {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { Ok(()) }
+        }
+        fn run(p: Point) {
+            p.to_stri~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "to_string");
+    }
+
+    #[test]
+    fn skips_blanket_impl_with_unsatisfied_bounds() {
+        let src = "
+        trait Shape {}
+        trait Describe {
+            fn describe(&self) -> String;
+        }
+        impl<T: Shape> Describe for T {
+            fn describe(&self) -> String { String::new() }
+        }
+        struct Square;
+        fn run(s: Square) {
+            s.descr~
+        }
+        ";
+        assert!(get_all_completions(src, None).is_empty());
+    }
+
+    #[test]
+    fn follows_bounds_in_where_clause() {
+        let src = "
+        trait Shape {}
+        trait Describe {
+            fn describe(&self) -> String;
+        }
+        impl<T> Describe for T where T: Shape {
+            fn describe(&self) -> String { String::new() }
+        }
+        struct Square;
+        struct Circle;
+        impl Shape for Circle {}
+        fn run(s: Square, c: Circle) {
+            s.descr~
+        }
+        ";
+        assert!(get_all_completions(src, None).is_empty());
+        let src = src.replace("s.descr~", "c.descr~");
+        assert_eq!(get_only_completion(&src, None).matchstr, "describe");
+    }
+
+    #[test]
+    fn completes_methods_from_blanket_impl_in_other_module_of_crate() {
+        let dir = setup_test_project();
+        let srcdir = dir.path().join("src");
+        fs::write(srcdir.join("lib.rs"), "pub mod describe;\npub mod src;").unwrap();
+        fs::write(
+            srcdir.join("describe.rs"),
+            "pub trait Shape {}
+            pub trait Describe {
+                fn describe(&self) -> String;
+            }
+            impl<T: Shape> Describe for T {
+                fn describe(&self) -> String { String::new() }
+            }",
+        ).unwrap();
+        let src = "
+        struct Square;
+        impl ::describe::Shape for Square {}
+        fn run(s: Square) {
+            s.descr~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir.nested_dir("src")));
+        assert_eq!(got.matchstr, "describe");
+        assert!(got.filepath.ends_with("describe.rs"));
+    }
+}
+
+mod trait_in_scope {
+    use super::*;
+    use std::fs;
+    #[test]
+    fn flags_method_of_trait_not_in_scope() {
+        let src = "
+        mod shapes {
+            pub trait Describe {
+                fn describe(&self);
+            }
+            pub struct Square;
+            impl Describe for Square {
+                fn describe(&self) {}
+            }
+        }
+        fn run(s: shapes::Square) {
+            s.descr~
+        }
+        ";
+        let got = get_only_completion(src, None);
+        assert_eq!(got.matchstr, "describe");
+        assert_eq!(got.trait_path, Some("crate::shapes::Describe".to_owned()));
+    }
+
+    #[test]
+    fn flags_method_of_dependency_trait_with_crate_name() {
+        let dir = setup_test_project();
+        let fixtures_src = dir.path().join("test_fixtures").join("src");
+        fs::write(
+            fixtures_src.join("shapes.rs"),
+            "pub trait Describe {
+                fn describe(&self);
+            }
+            pub struct Square;
+            impl Describe for Square {
+                fn describe(&self) {}
+            }",
+        ).unwrap();
+        fs::write(fixtures_src.join("lib.rs"), "pub mod shapes;").unwrap();
+        let src = "
+        extern crate fixtures;
+        fn run(s: fixtures::shapes::Square) {
+            s.descr~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir.nested_dir("src")));
+        assert_eq!(got.matchstr, "describe");
+        assert_eq!(got.trait_path, Some("fixtures::shapes::Describe".to_owned()));
+    }
+
+    #[test]
+    fn does_not_flag_method_of_imported_trait() {
+        let src = "
+        mod shapes {
+            pub trait Describe {
+                fn describe(&self);
+            }
+            pub struct Square;
+            impl Describe for Square {
+                fn describe(&self) {}
+            }
+        }
+        use shapes::Describe;
+        fn run(s: shapes::Square) {
+            s.descr~
+        }
+        ";
+        let got = get_only_completion(src, None);
+        assert_eq!(got.matchstr, "describe");
+        assert_eq!(got.trait_path, None);
+    }
+
+    #[test]
+    fn does_not_flag_inherent_method() {
+        let src = "
+        struct Square;
+        impl Square {
+            fn area(&self) -> u32 { 1 }
+        }
+        fn run(s: Square) {
+            s.are~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).trait_path, None);
+    }
+
+    #[test]
+    fn flags_std_trait_method_with_its_path() {
+        let src = "
+        fn run(f: std::fs::File) {
+            f.write_al~
+        }
+        ";
+        let got = get_only_completion(src, None);
+        assert_eq!(got.matchstr, "write_all");
+        assert_eq!(got.trait_path, Some("std::io::Write".to_owned()));
+    }
+
+    #[test]
+    fn flags_std_trait_method_with_its_exported_path() {
+        let src = "
+        struct Meters;
+        impl std::ops::Add for Meters {
+            type Output = Meters;
+            fn add(self, other: Meters) -> Meters { other }
+        }
+        fn run(m: Meters) {
+            m.ad~
+        }
+        ";
+        let got = get_only_completion(src, None);
+        assert_eq!(got.matchstr, "add");
+        assert_eq!(got.trait_path, Some("std::ops::Add".to_owned()));
+    }
+
+    /// A project whose `fixtures` dependency defines `Describe` in a private module
+    fn setup_private_trait_module(lib_src: &str) -> TmpDir {
+        let dir = setup_test_project();
+        let fixtures_src = dir.path().join("test_fixtures").join("src");
+        fs::write(
+            fixtures_src.join("shapes.rs"),
+            "pub trait Describe {
+                fn describe(&self);
+            }
+            pub struct Square;
+            impl Describe for Square {
+                fn describe(&self) {}
+            }",
+        ).unwrap();
+        fs::write(fixtures_src.join("lib.rs"), lib_src).unwrap();
+        dir
+    }
+
+    #[test]
+    fn flags_method_of_dependency_trait_with_reexported_path() {
+        let dir = setup_private_trait_module("mod shapes;\npub use shapes::{Describe, Square};");
+        let src = "
+        extern crate fixtures;
+        fn run(s: fixtures::Square) {
+            s.descr~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir.nested_dir("src")));
+        assert_eq!(got.matchstr, "describe");
+        assert_eq!(got.trait_path, Some("fixtures::Describe".to_owned()));
+    }
+
+    #[test]
+    fn flags_method_of_unexported_trait_with_its_name() {
+        let dir = setup_private_trait_module("mod shapes;\npub use shapes::Square;");
+        let src = "
+        extern crate fixtures;
+        fn run(s: fixtures::Square) {
+            s.descr~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir.nested_dir("src")));
+        assert_eq!(got.matchstr, "describe");
+        assert_eq!(got.trait_path, Some("Describe".to_owned()));
+    }
+}
+
+mod visibility {
+    use super::*;
+    use std::fs;
+    #[test]
+    fn skips_private_fields_from_outside_module() {
+        let src = "
+        mod shapes {
+            pub struct Rect {
+                pub width: u32,
+                wide_flag: bool,
+            }
+        }
+        fn run(r: shapes::Rect) {
+            r.wi~
+        }
+        ";
+        let got = get_all_completions(src, None);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].matchstr, "width");
+    }
+
+    #[test]
+    fn completes_private_fields_in_same_module() {
+        let src = "
+        struct Rect {
+            width: u32,
+            wide_flag: bool,
+        }
+        fn run(r: Rect) {
+            r.wi~
+        }
+        ";
+        assert_eq!(get_all_completions(src, None).len(), 2);
+    }
+
+    #[test]
+    fn completes_private_methods_from_child_module() {
+        let src = "
+        struct Rect;
+        impl Rect {
+            fn area(&self) -> u32 { 1 }
+        }
+        mod tests {
+            fn run(r: super::Rect) {
+                r.are~
+            }
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "area");
+    }
+
+    #[test]
+    fn skips_private_methods_from_outside_module() {
+        let src = "
+        mod shapes {
+            pub struct Rect;
+            impl Rect {
+                pub fn area_total(&self) -> u32 { 1 }
+                fn area_cached(&self) -> u32 { 1 }
+            }
+        }
+        fn run(r: shapes::Rect) {
+            r.are~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "area_total");
+    }
+
+    #[test]
+    fn follows_restricted_visibility() {
+        let src = "
+        mod outer {
+            pub mod shapes {
+                pub struct Rect;
+                impl Rect {
+                    pub(super) fn area_super(&self) -> u32 { 1 }
+                    pub(crate) fn area_crate(&self) -> u32 { 1 }
+                    pub(in outer::shapes) fn area_in(&self) -> u32 { 1 }
+                }
+            }
+        }
+        fn run(r: outer::shapes::Rect) {
+            r.are~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "area_crate");
+    }
+
+    #[test]
+    fn skips_crate_visible_methods_of_dependency() {
+        let dir = setup_test_project();
+        let fixtures_src = dir.path().join("test_fixtures").join("src");
+        fs::write(
+            fixtures_src.join("shapes.rs"),
+            "pub struct Rect;
+            impl Rect {
+                pub fn area_total(&self) -> u32 { 1 }
+                pub(crate) fn area_cached(&self) -> u32 { 1 }
+            }",
+        ).unwrap();
+        fs::write(fixtures_src.join("lib.rs"), "pub mod shapes;").unwrap();
+        let src = "
+        extern crate fixtures;
+        fn run(r: fixtures::shapes::Rect) {
+            r.are~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir.nested_dir("src")));
+        assert_eq!(got.matchstr, "area_total");
+    }
+
+    #[test]
+    fn skips_private_items_in_path_completion() {
+        let src = "
+        mod shapes {
+            pub fn make_rect() {}
+            fn make_secret() {}
+        }
+        fn run() {
+            shapes::make~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "make_rect");
+    }
+}
+
+mod cfg_attrs {
+    use super::*;
+    #[test]
+    fn completes_only_items_for_the_target() {
+        let src = "
+        #[cfg(unix)]
+        fn platform_name() -> &'static str { \"unix\" }
+        #[cfg(windows)]
+        #[inline]
+        fn platform_name() -> &'static str { \"windows\" }
+        fn main() {
+            platform_na~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "platform_name");
+    }
+
+    #[test]
+    fn follows_default_features_of_manifest() {
+        let dir = TmpDir::new();
+        let _manifest = dir.write_file(
+            "Cargo.toml",
+            "
+[package]
+name = \"shapes\"
+version = \"0.1.0\"
+
+[features]
+default = [\"circle\"]
+circle = []
+square = []
+",
+        );
+        let src = "
+        #[cfg(feature = \"circle\")]
+        fn draw_circle() {}
+        #[cfg(all(feature = \"square\", not(feature = \"circle\")))]
+        fn draw_square() {}
+        fn main() {
+            draw_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, Some(dir)).matchstr, "draw_circle");
+    }
+
+    #[test]
+    fn follows_features_of_session() {
+        let src = "
+        #[cfg(feature = \"circle\")]
+        fn draw_circle() {}
+        #[cfg(any(feature = \"square\", target_os = \"redox\"))]
+        fn draw_square() {}
+        fn main() {
+            draw_~
+        }
+        ";
+        let (pos, src) = get_pos_and_source(src);
+        let f = TmpFile::new(&src);
+        let cache = racer::FileCache::default();
+        let cfg = racer::Cfg {
+            features: Some(vec!["square".to_owned()].into_iter().collect()),
+            ..racer::Cfg::default()
+        };
+        let session = racer::Session::with_cfg(&cache, cfg);
+        let got: Vec<_> = complete_from_file(f.path(), pos, &session).collect();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].matchstr, "draw_square");
+    }
+}
+
+mod cfg_test {
+    use super::*;
+    #[test]
+    fn completes_items_of_test_module() {
+        let src = "
+        fn setup_prod() {}
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            fn setup_fixture() {}
+            #[test]
+            fn it_works() {
+                setup_f~
+            }
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "setup_fixture");
+    }
+
+    #[test]
+    fn completes_test_only_items_through_glob_import_of_super() {
+        let src = "
+        #[cfg(test)]
+        fn setup_fixture() {}
+        fn setup_prod() {}
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            fn run() {
+                setup_~
+            }
+        }
+        ";
+        let got: Vec<_> = get_all_completions(src, None)
+            .into_iter()
+            .map(|m| m.matchstr)
+            .collect();
+        assert_eq!(got, vec!["setup_fixture", "setup_prod"]);
+    }
+
+    #[test]
+    fn skips_test_only_items_outside_tests() {
+        let src = "
+        #[cfg(test)]
+        fn setup_fixture() {}
+        fn setup_prod() {}
+        fn main() {
+            setup_~
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "setup_prod");
+    }
+}
+
+mod path_attribute {
+    use super::*;
+    #[test]
+    fn completes_module_declared_with_path_attribute() {
+        let dir = TmpDir::new();
+        let platform = dir.nested_dir("platform");
+        let _linux = platform.write_file("linux.rs", "pub fn page_size() -> usize { 4096 }");
+        let src = "
+        #[path = \"platform/linux.rs\"]
+        #[allow(dead_code)]
+        mod imp;
+        fn main() {
+            imp::page_s~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir));
+        assert_eq!(got.matchstr, "page_size");
+        assert!(got.filepath.ends_with("platform/linux.rs"));
+    }
+
+    #[test]
+    fn resolves_path_attribute_in_inline_module() {
+        let dir = TmpDir::new();
+        let src_dir = dir.nested_dir("src");
+        let sys_dir = src_dir.nested_dir("sys");
+        let _unix = sys_dir.write_file("unix_impl.rs", "pub fn page_size() -> usize { 4096 }");
+        let src = "
+        mod sys {
+            #[path = \"unix_impl.rs\"]
+            pub mod imp;
+        }
+        fn main() {
+            sys::imp::page_s~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir));
+        assert!(got.filepath.ends_with("src/sys/unix_impl.rs"));
+    }
+
+    #[test]
+    fn finds_definition_of_module_with_path_attribute() {
+        let dir = TmpDir::new();
+        let _linux = dir.write_file("linux_sys.rs", "pub fn page_size() -> usize { 4096 }");
+        let src = "
+        #[path = \"linux_sys.rs\"]
+        mod imp;
+        fn main() {
+            imp::page_si~ze();
+        }
+        ";
+        let got = get_definition(src, Some(dir));
+        assert_eq!(got.matchstr, "page_size");
+        assert!(got.filepath.ends_with("linux_sys.rs"));
+    }
+}
+
+mod non_mod_rs_layout {
+    use super::*;
+    #[test]
+    fn completes_submodule_in_directory_named_after_file() {
+        let dir = TmpDir::new();
+        let src_dir = dir.nested_dir("src");
+        let _shapes = src_dir.write_file("shapes.rs", "pub mod circle;");
+        let shapes_dir = src_dir.nested_dir("shapes");
+        let _circle = shapes_dir.write_file("circle.rs", "pub fn circle_area() {}");
+        let src = "
+        mod shapes;
+        fn main() {
+            shapes::circle::circle_a~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir));
+        assert!(got.filepath.ends_with("shapes/circle.rs"));
+    }
+
+    #[test]
+    fn resolves_super_to_non_mod_rs_parent() {
+        let dir = TmpDir::new();
+        let _shapes = dir.write_file("shapes.rs", "pub mod circle;\npub fn make_shape() {}");
+        let shapes_dir = dir.nested_dir("shapes");
+        let src = "
+        fn draw() {
+            super::make_sh~
+        }
+        ";
+        let (pos, src) = get_pos_and_source(src);
+        let circle = shapes_dir.write_file("circle.rs", &src);
+        let cache = racer::FileCache::default();
+        let session = racer::Session::new(&cache);
+        let got: Vec<_> = complete_from_file(circle.path(), pos, &session).collect();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].matchstr, "make_shape");
+        assert!(got[0].filepath.ends_with("shapes.rs"));
+    }
+
+    #[test]
+    fn completes_submodule_of_integration_test_next_to_it() {
+        let dir = TmpDir::new();
+        dir.write_path(
+            "Cargo.toml",
+            "[package]\nname = \"shapes\"\nversion = \"0.1.0\"\n",
+        );
+        dir.write_path("src/lib.rs", "");
+        dir.write_path("tests/circle.rs", "pub fn circle_area() {}");
+        // not a module of `tests/draw.rs`, which is a crate root
+        dir.write_path("tests/draw/circle.rs", "pub fn circle_radius() {}");
+        let src = "
+        mod circle;
+        fn main() {
+            circle::circle_~
+        }
+        ";
+        let (completion_point, clean_src) = get_pos_and_source(src);
+        let path = dir.write_path("tests/draw.rs", &clean_src);
+        let cache = racer::FileCache::default();
+        let session = racer::Session::new(&cache);
+        let got: Vec<_> = complete_from_file(&path, completion_point, &session).collect();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].matchstr, "circle_area");
+    }
+
+    #[test]
+    fn completes_submodule_of_main_rs_module_in_its_directory() {
+        let dir = TmpDir::new();
+        dir.write_path(
+            "Cargo.toml",
+            "[package]\nname = \"shapes\"\nversion = \"0.1.0\"\n",
+        );
+        dir.write_path("src/lib.rs", "mod app;");
+        dir.write_path("src/app.rs", "mod main;");
+        // `src/app/main.rs` is a module, so its submodules are in `src/app/main/`
+        dir.write_path("src/app/main/circle.rs", "pub fn circle_area() {}");
+        let src = "
+        mod circle;
+        fn draw() {
+            circle::circle_a~
+        }
+        ";
+        let (completion_point, clean_src) = get_pos_and_source(src);
+        let path = dir.write_path("src/app/main.rs", &clean_src);
+        let cache = racer::FileCache::default();
+        let session = racer::Session::new(&cache);
+        let got: Vec<_> = complete_from_file(&path, completion_point, &session).collect();
+        assert_eq!(got.len(), 1);
+        assert!(got[0].filepath.ends_with("src/app/main/circle.rs"));
+    }
+}
+
+mod edition_2018 {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn resolves_crate_relative_path() {
+        let src = "
+        mod shapes {
+            pub fn make_circle() {}
+        }
+        mod canvas {
+            fn draw() {
+                crate::shapes::make_c~
+            }
+        }
+        ";
+        assert_eq!(get_only_completion(src, None).matchstr, "make_circle");
+    }
+
+    #[test]
+    fn completes_dependency_without_extern_crate() {
+        let dir = setup_test_project();
+        let manifest = "
+cargo-features = [\"edition\"]
+
+[package]
+name = \"test_project\"
+version = \"0.1.0\"
+edition = \"2018\"
+
+[dependencies]
+test_fixtures = { path = \"./test_fixtures\" }
+test-crate2 = { path = \"./test-crate2\"}
+";
+        fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+        let src = "
+        use fixtures::foo;
+        fn main() {
+            fixtures::ba~
+        }
+        ";
+        let srcdir = dir.nested_dir("src");
+        let got = get_only_completion(src, Some(srcdir));
+        assert_eq!(got.matchstr, "bar");
+    }
+
+    #[test]
+    fn completes_dependency_without_extern_crate_in_2021() {
+        let dir = setup_test_project();
+        let manifest = "
+[package]
+name = \"test_project\"
+version = \"0.1.0\"
+edition = \"2021\"
+
+[dependencies]
+test_fixtures = { path = \"./test_fixtures\" }
+";
+        fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+        let src = "
+        fn main() {
+            fixtures::ba~
+        }
+        ";
+        let srcdir = dir.nested_dir("src");
+        let got = get_only_completion(src, Some(srcdir));
+        assert_eq!(got.matchstr, "bar");
+    }
+
+    #[test]
+    fn ignores_dependencies_without_extern_crate_in_2015() {
+        let src = "
+        fn main() {
+            fixtures::ba~
+        }
+        ";
+        let dir = setup_test_project();
+        let srcdir = dir.nested_dir("src");
+        assert!(get_all_completions(src, Some(srcdir)).is_empty());
+    }
+
+    #[test]
+    fn resolves_crate_relative_path_from_binary_target() {
+        let src = "
+        mod shapes {
+            pub fn make_circle() {}
+        }
+        fn main() {
+            crate::shapes::make_c~
+        }
+        ";
+        // `src/bin/src.rs` is a crate of its own, while `src/lib.rs` has no `shapes`
+        let dir = setup_test_project();
+        let bindir = dir.nested_dir("src").nested_dir("bin");
+        assert_eq!(get_only_completion(src, Some(bindir)).matchstr, "make_circle");
+    }
+}
+
+mod own_library {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn completes_own_library_from_integration_test() {
+        let dir = setup_test_project();
+        fs::write(
+            dir.path().join("src").join("lib.rs"),
+            "pub fn make_project() {}",
+        ).unwrap();
+        let src = "
+        extern crate test_project;
+        fn main() {
+            test_project::make_~
+        }
+        ";
+        let testdir = dir.nested_dir("tests");
+        let got = get_only_completion(src, Some(testdir));
+        assert_eq!(got.matchstr, "make_project");
+        assert!(got.filepath.ends_with("src/lib.rs"));
+    }
+}
+
+mod dependency_kinds {
+    use super::*;
+    use std::fs;
+
+    fn setup_project_with_dev_dependency() -> TmpDir {
+        let dir = setup_test_project();
+        let manifest = "
+[package]
+name = \"test_project\"
+version = \"0.1.0\"
+
+[dev-dependencies]
+test_fixtures = { path = \"./test_fixtures\" }
+
+[build-dependencies]
+test-crate2 = { path = \"./test-crate2\"}
+";
+        fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+        dir
+    }
+
+    #[test]
+    fn completes_dev_dependency_from_integration_test() {
+        let src = "
+        extern crate fixtures;
+        fn main() {
+            fixtures::fo~
+        }
+        ";
+        let dir = setup_project_with_dev_dependency();
+        let testdir = dir.nested_dir("tests");
+        assert_eq!(get_only_completion(src, Some(testdir)).matchstr, "foo");
+    }
+
+    #[test]
+    fn skips_dev_dependency_from_library() {
+        let src = "
+        extern crate fixtures;
+        fn main() {
+            fixtures::fo~
+        }
+        ";
+        let dir = setup_project_with_dev_dependency();
+        let srcdir = dir.nested_dir("src");
+        assert!(get_all_completions(src, Some(srcdir)).is_empty());
+    }
+
+    #[test]
+    fn skips_build_dependency_from_library() {
+        let src = "
+        extern crate test_crate2;
+        fn main() {
+            test_crate2::~
+        }
+        ";
+        let dir = setup_project_with_dev_dependency();
+        let srcdir = dir.nested_dir("src");
+        assert!(get_all_completions(src, Some(srcdir)).is_empty());
+    }
+
+    #[test]
+    fn completes_dev_dependency_from_unit_tests_of_library() {
+        let src = "
+        #[cfg(test)]
+        mod tests {
+            extern crate fixtures;
+            fn test() {
+                fixtures::fo~
+            }
+        }
+        ";
+        let dir = setup_project_with_dev_dependency();
+        let srcdir = dir.nested_dir("src");
+        assert_eq!(get_only_completion(src, Some(srcdir)).matchstr, "foo");
+    }
+
+    #[test]
+    fn completes_package_library_from_module_of_binary() {
+        let dir = setup_test_project();
+        fs::write(
+            dir.path().join("src").join("lib.rs"),
+            "pub fn make_project() {}",
+        ).unwrap();
+        // the completed file is `src/src.rs`
+        fs::write(dir.path().join("src").join("main.rs"), "mod src;\nfn main() {}").unwrap();
+        let src = "
+        extern crate test_project;
+        fn run() {
+            test_project::make_~
+        }
+        ";
+        let srcdir = dir.nested_dir("src");
+        assert_eq!(get_only_completion(src, Some(srcdir)).matchstr, "make_project");
+    }
+}
+
+mod workspace_members {
+    use super::*;
+
+    #[test]
+    fn completes_member_dependency_from_other_member() {
+        let dir = TmpDir::new();
+        dir.write_path("Cargo.toml", "[workspace]\nmembers = [\"app\", \"shapes\"]\n");
+        dir.write_path(
+            "app/Cargo.toml",
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n\
+             [dependencies]\nshapes = { path = \"../shapes\" }\n",
+        );
+        dir.write_path("app/src/lib.rs", "extern crate shapes;");
+        dir.write_path(
+            "shapes/Cargo.toml",
+            "[package]\nname = \"shapes\"\nversion = \"0.1.0\"\n",
+        );
+        dir.write_path("shapes/src/lib.rs", "pub fn make_shape() {}");
+        let src = "
+        extern crate shapes;
+        use shapes::make_sh~
+        ";
+        let appdir = dir.nested_dir("app").nested_dir("src");
+        let got = get_only_completion(src, Some(appdir));
+        assert_eq!(got.matchstr, "make_shape");
+        assert!(got.filepath.ends_with("shapes/src/lib.rs"));
+    }
+
+    #[test]
+    fn finds_package_created_during_session() {
+        let dir = TmpDir::new();
+        dir.write_path(
+            "shapes/Cargo.toml",
+            "[package]\nname = \"shapes\"\nversion = \"0.1.0\"\n",
+        );
+        dir.write_path("shapes/src/lib.rs", "pub fn make_shape() {}");
+        let src = "
+        extern crate shapes;
+        use shapes::make_sh~
+        ";
+        let (completion_point, clean_src) = get_pos_and_source(src);
+        let path = dir.write_path("app/src/lib.rs", &clean_src);
+        let cache = racer::FileCache::default();
+        let session = racer::Session::new(&cache);
+        assert_eq!(complete_from_file(&path, completion_point, &session).count(), 0);
+
+        dir.write_path(
+            "app/Cargo.toml",
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n\
+             [dependencies]\nshapes = { path = \"../shapes\" }\n",
+        );
+        let got: Vec<_> = complete_from_file(&path, completion_point, &session).collect();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].matchstr, "make_shape");
+    }
+}
+
+mod offline_deps {
+    use super::*;
+    use racer::{DepsResolver, FileCache, Session};
+
+    #[test]
+    fn completes_vendored_dependency_from_lock_file() {
+        let dir = TmpDir::new();
+        dir.write_path(
+            "Cargo.toml",
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n\
+             [dependencies]\nracer-vendored-shapes = \"0.3\"\n",
+        );
+        dir.write_path(
+            "Cargo.lock",
+            "[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n\
+             dependencies = [\n \"racer-vendored-shapes 0.3.1 (registry+https://github.com/rust-lang/crates.io-index)\",\n]\n\n\
+             [[package]]\nname = \"racer-vendored-shapes\"\nversion = \"0.3.1\"\n\
+             source = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+        );
+        dir.write_path("src/lib.rs", "extern crate racer_vendored_shapes;");
+        dir.write_path(
+            "vendor/racer-vendored-shapes/Cargo.toml",
+            "[package]\nname = \"racer-vendored-shapes\"\nversion = \"0.3.1\"\n",
+        );
+        dir.write_path(
+            "vendor/racer-vendored-shapes/src/lib.rs",
+            "pub fn make_vendored_shape() {}",
+        );
+        let src = "
+        extern crate racer_vendored_shapes;
+        use racer_vendored_shapes::make_ven~
+        ";
+        let (completion_point, clean_src) = get_pos_and_source(src);
+        let path = dir.nested_dir("src").write_file("src.rs", &clean_src);
+        let mut cache = FileCache::default();
+        cache.set_deps_resolver(DepsResolver::Offline);
+        let session = Session::new(&cache);
+        let mut all: Vec<_> = complete_from_file(&path, completion_point, &session).collect();
+        assert_eq!(all.len(), 1);
+        let got = all.pop().unwrap();
+        assert_eq!(got.matchstr, "make_vendored_shape");
+        assert!(got.filepath.ends_with("vendor/racer-vendored-shapes/src/lib.rs"));
+    }
+}
+
+mod crate_aliases {
+    use super::*;
+
+    #[test]
+    fn completes_items_of_extern_crate_alias() {
+        let src = "
+        extern crate fixtures as fx;
+        use fx::f~
+        ";
+        let dir = setup_test_project();
+        let got = get_only_completion(src, Some(dir.nested_dir("src")));
+        assert_eq!(got.matchstr, "foo");
+    }
+
+    #[test]
+    fn finds_definition_of_extern_crate_alias() {
+        let src = "
+        extern crate fixtures as fx;
+        f~x::foo::test();
+        ";
+        let dir = setup_test_project();
+        let got = get_definition(src, Some(dir.nested_dir("src")));
+        assert_eq!(got.matchstr, "fx");
+        assert!(got.filepath.ends_with("test_fixtures/src/lib.rs"));
+    }
+
+    #[test]
+    fn completes_renamed_dependency() {
+        let dir = TmpDir::new();
+        dir.write_path(
+            "app/Cargo.toml",
+            "cargo-features = [\"rename-dependency\"]\n\n\
+             [package]\nname = \"app\"\nversion = \"0.1.0\"\n\n\
+             [dependencies]\nfigures = { package = \"shapes\", path = \"../shapes\" }\n",
+        );
+        dir.write_path("app/src/lib.rs", "extern crate figures;");
+        dir.write_path(
+            "shapes/Cargo.toml",
+            "[package]\nname = \"shapes\"\nversion = \"0.1.0\"\n",
+        );
+        dir.write_path("shapes/src/lib.rs", "pub fn make_shape() {}");
+        let src = "
+        extern crate figures;
+        use figures::make_sh~
+        ";
+        let appdir = dir.nested_dir("app").nested_dir("src");
+        let got = get_only_completion(src, Some(appdir));
+        assert_eq!(got.matchstr, "make_shape");
+        assert!(got.filepath.ends_with("shapes/src/lib.rs"));
+    }
+}
+
+mod rust_toolchain {
+    use super::*;
+
+    #[test]
+    fn completes_std_of_custom_toolchain_path() {
+        let dir = TmpDir::new();
+        dir.write_path(
+            "rust-toolchain.toml",
+            "[toolchain]\npath = \"custom-toolchain\"\n",
+        );
+        dir.write_path(
+            "custom-toolchain/lib/rustlib/src/rust/library/std/src/lib.rs",
+            "pub mod toolchain_marker {
+                pub fn marked_by_custom_toolchain() {}
+            }",
+        );
+        let src = "
+        fn main() {
+            std::toolchain_marker::marked_~
+        }
+        ";
+        let got = get_only_completion(src, Some(dir));
+        assert_eq!(got.matchstr, "marked_by_custom_toolchain");
+        assert!(got.filepath.ends_with("library/std/src/lib.rs"));
+    }
+}
+
+mod no_std {
+    use super::*;
+
+    #[test]
+    fn completes_core_instead_of_std() {
+        let src = "
+        #![no_std]
+        use co~
+        ";
+        let got = get_all_completions(src, None);
+        assert!(got.iter().any(|m| m.matchstr == "core"), "{:?}", got);
+        let src = "
+        #![no_std]
+        use st~
+        ";
+        let got = get_all_completions(src, None);
+        assert!(got.iter().all(|m| m.matchstr != "std"), "{:?}", got);
+    }
+
+    #[test]
+    fn uses_prelude_of_core() {
+        let src = "
+        #![no_std]
+        fn main() {
+            let opt: Opti~
+        }
+        ";
+        let got = get_only_completion(src, None);
+        assert_eq!(got.matchstr, "Option");
+        let src = "
+        #![no_std]
+        fn main() {
+            let v: Ve~
+        }
+        ";
+        let got = get_all_completions(src, None);
+        assert!(got.iter().all(|m| m.matchstr != "Vec"), "{:?}", got);
+    }
+
+    #[test]
+    fn completes_alloc_with_extern_crate() {
+        let src = "
+        #![no_std]
+        extern crate alloc;
+        use alloc::vec::Ve~
+        ";
+        let got = get_all_completions(src, None);
+        assert!(got.iter().any(|m| m.matchstr == "Vec"), "{:?}", got);
+    }
+
+    #[test]
+    fn finds_alloc_path_with_extern_crate() {
+        let src = "
+        #![no_std]
+        extern crate alloc;
+        fn main() {
+            let v: alloc::vec::Ve~c<u8> = alloc::vec::Vec::new();
+        }
+        ";
+        let got = get_definition(src, None);
+        assert_eq!(got.matchstr, "Vec");
+        let filepath = got.filepath.to_str().unwrap();
+        assert!(filepath.contains("alloc"), "{}", filepath);
+    }
+
+    #[test]
+    fn completes_std_items_with_extern_crate_std() {
+        let src = "
+        #![no_std]
+        extern crate std;
+        use std::collections::HashM~
+        ";
+        let got = get_all_completions(src, None);
+        assert!(got.iter().any(|m| m.matchstr == "HashMap"), "{:?}", got);
+    }
+
+    #[test]
+    fn detects_no_std_in_cfg_attr() {
+        let src = "
+        #![cfg_attr(not(feature = \"std\"), no_std)]
+        fn main() {
+            let v: Ve~
+        }
+        ";
+        let got = get_all_completions(src, None);
+        assert!(got.iter().all(|m| m.matchstr != "Vec"), "{:?}", got);
+    }
+
+    #[test]
+    fn completes_vec_in_std_crate() {
+        let src = "
+        fn main() {
+            let v: Ve~
+        }
+        ";
+        let got = get_all_completions(src, None);
+        assert!(got.iter().any(|m| m.matchstr == "Vec"), "{:?}", got);
+    }
+
+    #[test]
+    fn flags_trait_method_with_path_in_core() {
+        let src = "
+        #![no_std]
+        struct Buf;
+        impl core::fmt::Write for Buf {
+            fn write_str(&mut self, s: &str) -> core::fmt::Result { Ok(()) }
+        }
+        mod output {
+            fn run(b: super::Buf) {
+                b.write_fm~
+            }
+        }
+        ";
+        let got = get_only_completion(src, None);
+        assert_eq!(got.matchstr, "write_fmt");
+        assert_eq!(got.trait_path, Some("core::fmt::Write".to_owned()));
+    }
+}
+
+mod symbol_index {
+    use super::*;
+    use racer::{FileCache, Match, Session};
+
+    const LIB_SRC: &str = "
+            /// A shape
+            pub struct Shape;
+            impl Shape {
+                pub fn area(&self) -> f64 { 0.0 }
+            }
+            pub fn make_shape() -> Shape { Shape }
+            ";
+
+    fn cache_with_index_dir(index_dir: &TmpDir) -> FileCache {
+        let mut cache = FileCache::default();
+        cache.set_index_dir(index_dir.path());
+        cache
+    }
+
+    /// Completes in `src.rs` of the directory with indexes read from the index directory
+    fn get_indexed_completions(src: &str, dir: &TmpDir, index_dir: &TmpDir) -> Vec<Match> {
+        let (completion_point, clean_src) = get_pos_and_source(src);
+        let path = dir.write_file("src.rs", &clean_src);
+        let cache = cache_with_index_dir(index_dir);
+        let session = Session::new(&cache);
+        complete_from_file(&path, completion_point, &session).collect()
+    }
+
+    #[test]
+    fn completes_items_of_indexed_files() {
+        let index_dir = TmpDir::new();
+        let dir = TmpDir::new();
+        let lib = dir.write_file("lib.rs", LIB_SRC);
+        {
+            let cache = cache_with_index_dir(&index_dir);
+            let session = Session::new(&cache);
+            assert_eq!(racer::build_index(dir.path(), &session).unwrap(), 1);
+        }
+        {
+            let cache = cache_with_index_dir(&index_dir);
+            let session = Session::new(&cache);
+            assert!(racer::get_file_index(lib.path(), &session).is_some());
+        }
+
+        let src = "
+        fn main() {
+            let s = make_shape();
+            s.ar~
+        }
+        ";
+        let got = get_indexed_completions(src, &dir, &index_dir);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].matchstr, "area");
+    }
+
+    #[test]
+    fn reads_source_of_files_edited_after_indexing() {
+        let index_dir = TmpDir::new();
+        let dir = TmpDir::new();
+        let _lib = dir.write_file("lib.rs", LIB_SRC);
+        {
+            let cache = cache_with_index_dir(&index_dir);
+            let session = Session::new(&cache);
+            assert_eq!(racer::build_index(dir.path(), &session).unwrap(), 1);
+        }
+        let lib = dir.write_file(
+            "lib.rs",
+            &format!("{}\npub fn make_circle() -> Shape {{ Shape }}\n", LIB_SRC),
+        );
+        {
+            let cache = cache_with_index_dir(&index_dir);
+            let session = Session::new(&cache);
+            assert!(racer::get_file_index(lib.path(), &session).is_none());
+        }
+
+        let src = "
+        fn main() {
+            make_ci~
+        }
+        ";
+        let got = get_indexed_completions(src, &dir, &index_dir);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].matchstr, "make_circle");
+    }
+}
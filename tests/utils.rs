@@ -93,6 +93,13 @@ impl TmpDir {
             .expect("couldn't write to temp file");
         TmpFile { inner: file }
     }
+    /// write `contents` to `relpath` under the directory, creating parent directories
+    pub fn write_path(&self, relpath: &str, contents: &str) -> PathBuf {
+        let path = self.path().join(relpath);
+        fs::create_dir_all(path.parent().unwrap()).expect("failed to create directories.");
+        fs::write(&path, contents).expect("couldn't write to file");
+        path
+    }
     pub fn path(&self) -> &Path {
         match self {
             TmpDir::Tmp(dir) => dir.path(),